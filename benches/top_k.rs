@@ -0,0 +1,66 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use tp1::top_n_tags_highest_ratio;
+
+/// Number of tags of the synthetic map.
+const TAGS: usize = 1_000_000;
+
+/// Build a map of `TAGS` tags with pseudo-random `(questions, words)` counts.
+fn synthetic_tag_counts() -> HashMap<String, (usize, usize)> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    (0..TAGS)
+        .map(|tag| {
+            let questions = (next() % 1_000 + 1) as usize;
+            let words = (next() % 100_000) as usize;
+            (format!("tag{}", tag), (questions, words))
+        })
+        .collect()
+}
+
+/// Select the top tags the way it was done before, cloning and sorting every ratio.
+fn full_sort_top_tags(tag_counts: &HashMap<String, (usize, usize)>, n: usize) -> Vec<String> {
+    let ratios: Vec<(&String, f64)> = tag_counts
+        .par_iter()
+        .map(|(tag, &(questions, words))| (tag, words as f64 / questions as f64))
+        .collect();
+
+    let mut sorted_ratios = ratios.clone();
+    sorted_ratios.sort_by(|(_, ratio1), (_, ratio2)| {
+        ratio2
+            .partial_cmp(ratio1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    sorted_ratios
+        .iter()
+        .take(n)
+        .map(|&(tag, _)| tag.clone())
+        .collect()
+}
+
+fn top_tags(c: &mut Criterion) {
+    let tag_counts = synthetic_tag_counts();
+
+    let mut group = c.benchmark_group("top_tags_1m");
+    group.sample_size(20);
+    for n in [10, 1_000] {
+        group.bench_with_input(BenchmarkId::new("full_sort", n), &n, |b, &n| {
+            b.iter(|| full_sort_top_tags(black_box(&tag_counts), n))
+        });
+        group.bench_with_input(BenchmarkId::new("bounded_heap", n), &n, |b, &n| {
+            b.iter(|| top_n_tags_highest_ratio(black_box(&tag_counts), n, 0))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, top_tags);
+criterion_main!(benches);
@@ -9,7 +9,7 @@ cd data
 # Este archivo es el mas grande, solo agrega complegidad logistica
 rm stackoverflow.com-Posts.jsonl.gz
 
+# Los archivos .jsonl.gz se procesan sin descomprimir
 for filename in *.gz; do
     curl -o $filename -L https://huggingface.co/datasets/flax-sentence-embeddings/stackexchange_title_body_jsonl/resolve/main/$filename 
-    gunzip -v $filename
-done
\ No newline at end of file
+done
@@ -1,7 +1,101 @@
-use crate::file_data::FileData;
+use crate::cooccurrence::{TagPairStats, TagPairs, TopTagPairs};
+use crate::dedup::{DuplicateCluster, QuestionRef};
+use crate::file_data::{FileData, TextCounts};
+use crate::heavy_hitters::HeavyHitters;
+use crate::sketch::{DistinctSketch, LengthSketch};
+use crate::terms::{top_n_terms_tf_idf, TermCounts, TermScore};
+use crate::top_k::par_top_k;
 use rayon::prelude::*;
 use std::collections::HashMap;
 
+/// Number of entries of the rankings when no other size is requested.
+pub const DEFAULT_TOP: usize = 10;
+
+/// Number of questions of the prior of `RankingMetric::SmoothedMean` when no other is requested.
+pub const DEFAULT_PRIOR_QUESTIONS: usize = 10;
+
+/// Metrics by which sites and tags are ranked.
+///
+/// Every ranking puts first the entries with the highest value of the metric.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RankingMetric {
+    /// Mean number of words per question.
+    #[default]
+    Mean,
+    /// Median number of words per question.
+    Median,
+    /// 90th percentile of the number of words per question.
+    P90,
+    /// Mean number of words per question shrunk toward the mean of all the ranked entries,
+    /// as if every entry had `prior_questions` more questions of that mean length.
+    SmoothedMean { prior_questions: usize },
+    /// Total number of words.
+    TotalWords,
+    /// Number of questions.
+    Questions,
+}
+
+impl RankingMetric {
+    /// Whether the metric needs the distribution of question lengths.
+    ///
+    /// Entries without `FileData::lengths` or `FileData::tag_lengths` score zero with
+    /// these metrics, so the files must be processed with `ProcessOptions::collect_lengths`.
+    ///
+    pub fn needs_lengths(&self) -> bool {
+        matches!(self, RankingMetric::Median | RankingMetric::P90)
+    }
+}
+
+/// Sizes, minimum support and metric of the rankings included in a result.
+///
+/// Sites and tags with fewer questions than the minimum of a ranking are left out of it,
+/// but are still part of the counts of the result.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankingOptions {
+    /// Number of chatty sites.
+    pub top_sites: usize,
+    /// Number of chatty tags across all sites.
+    pub top_tags: usize,
+    /// Number of chatty tags of each site.
+    pub top_site_tags: usize,
+    /// Minimum number of questions of a chatty site.
+    pub min_questions_sites: usize,
+    /// Minimum number of questions of a chatty tag across all sites.
+    pub min_questions_tags: usize,
+    /// Minimum number of questions of a tag in a site to be one of its chatty tags.
+    pub min_questions_site_tags: usize,
+    /// Number of top pairs of tags, across all sites and of each site.
+    pub top_tag_pairs: usize,
+    /// Minimum number of questions in which a pair of tags appears together to be ranked.
+    pub min_questions_tag_pairs: usize,
+    /// Number of distinctive terms of every site and tag.
+    pub top_terms: usize,
+    /// Number of most frequent words, across all sites and of each site.
+    pub top_words: usize,
+    /// Metric by which the entries of every ranking are ordered.
+    pub metric: RankingMetric,
+}
+
+impl Default for RankingOptions {
+    fn default() -> Self {
+        RankingOptions {
+            top_sites: DEFAULT_TOP,
+            top_tags: DEFAULT_TOP,
+            top_site_tags: DEFAULT_TOP,
+            min_questions_sites: 0,
+            min_questions_tags: 0,
+            min_questions_site_tags: 0,
+            top_tag_pairs: DEFAULT_TOP,
+            min_questions_tag_pairs: 0,
+            top_terms: DEFAULT_TOP,
+            top_words: DEFAULT_TOP,
+            metric: RankingMetric::Mean,
+        }
+    }
+}
+
 /// Aggregate tag counts from multiple files.
 ///
 /// This function aggregates tag counts from multiple files into a single HashMap.
@@ -39,11 +133,236 @@ pub fn aggregate_tag_counts(files: &Vec<FileData>) -> HashMap<String, (usize, us
         })
 }
 
+/// Aggregate the question length distributions of the tags of multiple files.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `tag_lengths` are aggregated.
+///
+/// # Returns
+///
+/// A HashMap with the distribution of the question lengths of every tag across all files.
+///
+pub fn aggregate_tag_lengths(files: &[FileData]) -> HashMap<String, LengthSketch> {
+    files
+        .par_iter()
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<String, LengthSketch>, file| {
+                for (tag, lengths) in &file.tag_lengths {
+                    acc.entry(tag.clone()).or_default().merge(lengths);
+                }
+                acc
+            },
+        )
+        .reduce(HashMap::new, |mut acc, map| {
+            for (tag, lengths) in map {
+                acc.entry(tag).or_default().merge(&lengths);
+            }
+            acc
+        })
+}
+
+/// Aggregate the words by text role of the tags of multiple files.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `tag_texts` are aggregated.
+///
+/// # Returns
+///
+/// A HashMap with the words by text role of every tag across all files.
+///
+pub fn aggregate_tag_texts(files: &[FileData]) -> HashMap<String, TextCounts> {
+    files
+        .par_iter()
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<String, TextCounts>, file| {
+                for (tag, texts) in &file.tag_texts {
+                    acc.entry(tag.clone()).or_default().merge(texts);
+                }
+                acc
+            },
+        )
+        .reduce(HashMap::new, |mut acc, map| {
+            for (tag, texts) in map {
+                acc.entry(tag).or_default().merge(&texts);
+            }
+            acc
+        })
+}
+
+/// Aggregate the pairs of tags of multiple files.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `tag_pairs` are aggregated.
+///
+/// # Returns
+///
+/// The `TagPairs` of all the questions of the files.
+///
+pub fn aggregate_tag_pairs(files: &[FileData]) -> TagPairs {
+    files
+        .par_iter()
+        .fold(TagPairs::default, |mut acc, file| {
+            acc.merge(&file.tag_pairs);
+            acc
+        })
+        .reduce(TagPairs::default, |mut acc, pairs| {
+            acc.merge(&pairs);
+            acc
+        })
+}
+
+/// Aggregate the terms of the tags of multiple files.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `tag_terms` are aggregated.
+///
+/// # Returns
+///
+/// A HashMap with the term counts of every tag across all files.
+///
+pub fn aggregate_tag_terms(files: &[FileData]) -> HashMap<String, TermCounts> {
+    files
+        .par_iter()
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<String, TermCounts>, file| {
+                for (tag, terms) in &file.tag_terms {
+                    acc.entry(tag.clone()).or_default().merge(terms);
+                }
+                acc
+            },
+        )
+        .reduce(HashMap::new, |mut acc, map| {
+            for (tag, terms) in map {
+                acc.entry(tag).or_default().merge(&terms);
+            }
+            acc
+        })
+}
+
+/// Aggregate the distinct word sketches of the tags of multiple files.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `tag_distinct_words` are aggregated.
+///
+/// # Returns
+///
+/// A HashMap with the distinct words of every tag across all files.
+///
+pub fn aggregate_tag_distinct_words(files: &[FileData]) -> HashMap<String, DistinctSketch> {
+    files
+        .par_iter()
+        .fold(
+            HashMap::new,
+            |mut acc: HashMap<String, DistinctSketch>, file| {
+                for (tag, distinct_words) in &file.tag_distinct_words {
+                    acc.entry(tag.clone()).or_default().merge(distinct_words);
+                }
+                acc
+            },
+        )
+        .reduce(HashMap::new, |mut acc, map| {
+            for (tag, distinct_words) in map {
+                acc.entry(tag).or_default().merge(&distinct_words);
+            }
+            acc
+        })
+}
+
+/// Aggregate the heavy hitter summaries of multiple files.
+///
+/// The summaries are merged and pruned in the order of `files`, so the result does not
+/// depend on the number of threads.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `heavy_hitters` are aggregated.
+///
+/// # Returns
+///
+/// The summary of the most frequent words across all files, or `None` if no file has one.
+///
+pub fn aggregate_heavy_hitters(files: &[FileData]) -> Option<HeavyHitters> {
+    let mut summaries = files.iter().filter_map(|file| file.heavy_hitters.as_ref());
+    let mut acc = summaries.next()?.clone();
+    for heavy_hitters in summaries {
+        acc.merge(heavy_hitters);
+        acc.prune();
+    }
+    Some(acc)
+}
+
+/// Gather the near-duplicates of multiple files into clusters.
+///
+/// Every cluster holds the question kept and its duplicates, in the order of `files` and
+/// by line. Larger clusters come first, and clusters of the same size are ordered by the
+/// question kept.
+///
+/// # Arguments
+///
+/// * `files`: A slice of `FileData` whose `duplicates` are gathered.
+///
+/// # Returns
+///
+/// The clusters of near-duplicate questions, empty if no file has duplicates.
+///
+pub fn aggregate_duplicate_clusters(files: &[FileData]) -> Vec<DuplicateCluster> {
+    let positions: HashMap<&str, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(position, file)| (file.filename.as_str(), position))
+        .collect();
+    let position = |question: &QuestionRef| {
+        let site = positions.get(question.site.as_str()).copied();
+        (site.unwrap_or(usize::MAX), question.line)
+    };
+
+    let mut clusters: HashMap<&QuestionRef, Vec<QuestionRef>> = HashMap::new();
+    for file in files {
+        for duplicate in &file.duplicates {
+            clusters
+                .entry(&duplicate.of)
+                .or_default()
+                .push(QuestionRef {
+                    site: file.filename.clone(),
+                    line: duplicate.line,
+                });
+        }
+    }
+
+    let mut clusters: Vec<DuplicateCluster> = clusters
+        .into_iter()
+        .map(|(kept, mut questions)| {
+            questions.sort_by_key(|question| position(question));
+            let across_sites = questions.iter().any(|question| question.site != kept.site);
+            questions.insert(0, kept.clone());
+            DuplicateCluster {
+                questions,
+                across_sites,
+            }
+        })
+        .collect();
+    clusters.sort_by(|cluster1, cluster2| {
+        cluster2
+            .questions
+            .len()
+            .cmp(&cluster1.questions.len())
+            .then_with(|| position(&cluster1.questions[0]).cmp(&position(&cluster2.questions[0])))
+    });
+    clusters
+}
+
 /// Find the top 10 tags with the highest ratio of words to questions.
 ///
-/// This function calculates the ratio of words to questions for each tag based on the aggregated
-/// tag counts and selects the top 10 tags with the highest ratio. The ratio is calculated as the
-/// total number of words divided by the total number of questions for each tag.
+/// This function is equivalent to `top_n_tags_highest_ratio` with `n` set to 10 and no
+/// minimum number of questions.
 ///
 /// # Arguments
 ///
@@ -56,33 +375,77 @@ pub fn aggregate_tag_counts(files: &Vec<FileData>) -> HashMap<String, (usize, us
 pub fn top_10_tags_highest_ratio(
     aggregated_tag_counts: &HashMap<String, (usize, usize)>,
 ) -> Vec<String> {
-    let ratios: Vec<(&String, f64)> = aggregated_tag_counts
-        .par_iter()
-        .map(|(tag, &(questions, words))| {
-            let ratio = if questions > 0 {
-                words as f64 / questions as f64
-            } else {
-                0.0
-            };
-            (tag, ratio)
-        })
-        .collect();
+    top_n_tags_highest_ratio(aggregated_tag_counts, DEFAULT_TOP, 0)
+}
 
-    let mut sorted_ratios = ratios.clone();
-    sorted_ratios.sort_by(|(_, ratio1), (_, ratio2)| {
-        match ratio2.partial_cmp(ratio1) {
-            Some(order) => order,
-            None => std::cmp::Ordering::Equal, // or any other appropriate behavior
-        }
-    });
+/// Find the top `n` tags with the highest ratio of words to questions.
+///
+/// This function calculates the ratio of words to questions for each tag based on the aggregated
+/// tag counts and selects the top `n` tags with the highest ratio. The ratio is calculated as the
+/// total number of words divided by the total number of questions for each tag.
+/// Tags with fewer than `min_questions` questions are not ranked, and tags with the same ratio
+/// are ranked by name. The tags are selected with `par_top_k`, without sorting all of them.
+///
+/// # Arguments
+///
+/// * `aggregated_tag_counts`: A reference to a HashMap containing aggregated tag counts.
+/// * `n`: The number of tags to select.
+/// * `min_questions`: The minimum number of questions of a ranked tag.
+///
+/// # Returns
+///
+/// A vector containing the names of the top `n` tags with the highest ratio of words to questions.
+///
+pub fn top_n_tags_highest_ratio(
+    aggregated_tag_counts: &HashMap<String, (usize, usize)>,
+    n: usize,
+    min_questions: usize,
+) -> Vec<String> {
+    top_n_tags_by(
+        aggregated_tag_counts,
+        &HashMap::new(),
+        n,
+        min_questions,
+        RankingMetric::Mean,
+    )
+}
 
-    let top_10_tags = sorted_ratios
-        .iter()
-        .take(10)
-        .map(|&(tag, _)| tag.clone())
-        .collect::<Vec<String>>();
+/// Find the top `n` tags with the highest value of a metric.
+///
+/// This function works like `top_n_tags_highest_ratio`, but ranks the tags by `metric`.
+/// The smoothed mean of a tag is shrunk toward the mean length of the questions of all tags.
+///
+/// # Arguments
+///
+/// * `aggregated_tag_counts`: A reference to a HashMap containing aggregated tag counts.
+/// * `tag_lengths`: The question length distributions of the tags, as returned by
+///   `aggregate_tag_lengths`. Only used by the metrics that need them.
+/// * `n`: The number of tags to select.
+/// * `min_questions`: The minimum number of questions of a ranked tag.
+/// * `metric`: The metric by which the tags are ranked.
+///
+/// # Returns
+///
+/// A vector containing the names of the top `n` tags with the highest value of the metric.
+///
+pub fn top_n_tags_by(
+    aggregated_tag_counts: &HashMap<String, (usize, usize)>,
+    tag_lengths: &HashMap<String, LengthSketch>,
+    n: usize,
+    min_questions: usize,
+    metric: RankingMetric,
+) -> Vec<String> {
+    let scorer = Scorer::new(metric, || {
+        sum_counts(aggregated_tag_counts.par_iter().map(|(_, &counts)| counts))
+    });
+    let scores = aggregated_tag_counts
+        .par_iter()
+        .filter(|(_, &(questions, _))| questions >= min_questions)
+        .map(|(tag, &(questions, words))| {
+            (tag, scorer.score(questions, words, tag_lengths.get(tag)))
+        });
 
-    top_10_tags
+    par_top_k(scores, n).into_iter().cloned().collect()
 }
 
 /// Calculate the ratio of words to questions for a file.
@@ -100,19 +463,13 @@ pub fn top_10_tags_highest_ratio(
 /// The ratio of words to questions as a floating-point number.
 ///
 pub fn words_questions_ratio(file_data: &FileData) -> f64 {
-    if file_data.total_line_count > 0 {
-        file_data.total_word_count as f64 / file_data.total_line_count as f64
-    } else {
-        0.0
-    }
+    ratio(file_data.total_line_count, file_data.total_word_count)
 }
 
 /// Find the top 10 filenames with the highest ratio of words to questions.
 ///
-/// This function calculates the ratio of words to questions for each file and selects the top
-/// 10 filenames with the highest ratio. The ratio is calculated as the total number of words
-/// divided by the total number of lines in the file. If the total number of lines is zero, the
-/// ratio is considered to be zero.
+/// This function is equivalent to `top_n_filenames_highest_ratio` with `n` set to 10 and no
+/// minimum number of questions.
 ///
 /// # Arguments
 ///
@@ -123,62 +480,352 @@ pub fn words_questions_ratio(file_data: &FileData) -> f64 {
 /// A vector containing the names of the top 10 filenames with the highest ratio of words to questions.
 ///
 pub fn top_10_filenames_highest_ratio(files_data: &[FileData]) -> Vec<String> {
-    let ratios: Vec<(String, f64)> = files_data
-        .par_iter()
-        .map(|file_data| (file_data.filename.clone(), words_questions_ratio(file_data)))
-        .collect();
+    top_n_filenames_highest_ratio(files_data, DEFAULT_TOP, 0)
+}
+
+/// Find the top `n` filenames with the highest ratio of words to questions.
+///
+/// This function calculates the ratio of words to questions for each file and selects the top
+/// `n` filenames with the highest ratio. The ratio is calculated as the total number of words
+/// divided by the total number of lines in the file. If the total number of lines is zero, the
+/// ratio is considered to be zero. Files with fewer than `min_questions` questions are not
+/// ranked, and files with the same ratio are ranked by filename.
+///
+/// # Arguments
+///
+/// * `files_data`: A slice containing references to `FileData` instances representing the file data.
+/// * `n`: The number of filenames to select.
+/// * `min_questions`: The minimum number of questions of a ranked file.
+///
+/// # Returns
+///
+/// A vector containing the names of the top `n` filenames with the highest ratio of words to questions.
+///
+pub fn top_n_filenames_highest_ratio(
+    files_data: &[FileData],
+    n: usize,
+    min_questions: usize,
+) -> Vec<String> {
+    top_n_filenames_by(files_data, n, min_questions, RankingMetric::Mean)
+}
 
-    let mut sorted_ratios = ratios.clone();
-    sorted_ratios.sort_by(|(_, ratio1), (_, ratio2)| {
-        ratio2
-            .partial_cmp(ratio1)
-            .unwrap_or(std::cmp::Ordering::Equal)
+/// Find the top `n` filenames with the highest value of a metric.
+///
+/// This function works like `top_n_filenames_highest_ratio`, but ranks the files by `metric`.
+/// The smoothed mean of a file is shrunk toward the mean length of the questions of all files.
+///
+/// # Arguments
+///
+/// * `files_data`: A slice containing references to `FileData` instances representing the file data.
+/// * `n`: The number of filenames to select.
+/// * `min_questions`: The minimum number of questions of a ranked file.
+/// * `metric`: The metric by which the files are ranked.
+///
+/// # Returns
+///
+/// A vector containing the names of the top `n` filenames with the highest value of the metric.
+///
+pub fn top_n_filenames_by(
+    files_data: &[FileData],
+    n: usize,
+    min_questions: usize,
+    metric: RankingMetric,
+) -> Vec<String> {
+    let scorer = Scorer::new(metric, || {
+        sum_counts(
+            files_data
+                .par_iter()
+                .map(|file_data| (file_data.total_line_count, file_data.total_word_count)),
+        )
     });
+    let scores = files_data
+        .par_iter()
+        .filter(|file_data| file_data.total_line_count >= min_questions)
+        .map(|file_data| {
+            let score = scorer.score(
+                file_data.total_line_count,
+                file_data.total_word_count,
+                file_data.lengths.as_ref(),
+            );
+            (&file_data.filename, score)
+        });
 
-    let top_10_filenames = sorted_ratios
-        .iter()
-        .take(10)
-        .map(|(filename, _)| filename.clone())
-        .collect::<Vec<String>>();
+    par_top_k(scores, n).into_iter().cloned().collect()
+}
 
-    top_10_filenames
+/// Generate the top 10 tags for each file.
+///
+/// This function is equivalent to `generate_top_n_tags` with `n` set to 10 and no minimum
+/// number of questions.
+///
+/// # Arguments
+///
+/// * `files_data`: A mutable slice of `FileData` instances representing the file data.
+///
+pub fn generate_top_tags(files_data: &mut [FileData]) {
+    generate_top_n_tags(files_data, DEFAULT_TOP, 0)
 }
 
-/// Generate the top tags for each file.
+/// Generate the top `n` tags for each file.
 ///
-/// This function generates the top tags for each file in the given vector of `FileData`.
+/// This function generates the top tags for each file in the given slice of `FileData`.
 /// It calculates the ratio of words to questions for each tag in each file's tag counts
-/// and selects the top 10 tags with the highest ratio. The ratio is calculated as the
+/// and selects the top `n` tags with the highest ratio. The ratio is calculated as the
 /// total number of words divided by the total number of questions for each tag. If the
-/// total number of questions is zero, the ratio is considered to be zero.
+/// total number of questions is zero, the ratio is considered to be zero. Tags with fewer
+/// than `min_questions` questions in the file are not ranked, and tags with the same ratio
+/// are ranked by name.
 ///
 /// # Arguments
 ///
-/// * `files_data`: A mutable reference to a vector of `FileData` instances representing the file data.
+/// * `files_data`: A mutable slice of `FileData` instances representing the file data.
+/// * `n`: The number of tags to select for each file.
+/// * `min_questions`: The minimum number of questions of a ranked tag in a file.
 ///
-pub fn generate_top_tags(files_data: &mut Vec<FileData>) {
+pub fn generate_top_n_tags(files_data: &mut [FileData], n: usize, min_questions: usize) {
+    generate_top_n_tags_by(files_data, n, min_questions, RankingMetric::Mean)
+}
+
+/// Generate the top `n` tags for each file by the value of a metric.
+///
+/// This function works like `generate_top_n_tags`, but ranks the tags by `metric`. The
+/// smoothed mean of a tag is shrunk toward the mean length of the questions of all the
+/// tags of its file.
+///
+/// # Arguments
+///
+/// * `files_data`: A mutable slice of `FileData` instances representing the file data.
+/// * `n`: The number of tags to select for each file.
+/// * `min_questions`: The minimum number of questions of a ranked tag in a file.
+/// * `metric`: The metric by which the tags are ranked.
+///
+pub fn generate_top_n_tags_by(
+    files_data: &mut [FileData],
+    n: usize,
+    min_questions: usize,
+    metric: RankingMetric,
+) {
     files_data.par_iter_mut().for_each(|file_data| {
-        let mut tag_ratios: Vec<(String, f64)> = file_data
+        let scorer = Scorer::new(metric, || {
+            sum_counts(file_data.tag_counts.par_iter().map(|(_, &counts)| counts))
+        });
+        let scores = file_data
             .tag_counts
             .par_iter()
-            .map(|(tag, (question_count, tag_word_count))| {
-                let ratio = if *question_count > 0 {
-                    *tag_word_count as f64 / *question_count as f64
-                } else {
-                    0.0
-                };
-                (tag.clone(), ratio)
-            })
-            .collect();
+            .filter(|(_, &(questions, _))| questions >= min_questions)
+            .map(|(tag, &(questions, words))| {
+                (
+                    tag,
+                    scorer.score(questions, words, file_data.tag_lengths.get(tag)),
+                )
+            });
 
-        tag_ratios.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        let top_tags: Vec<String> = tag_ratios
-            .into_iter()
-            .map(|(tag, _)| tag)
-            .take(10)
-            .collect();
+        let top_tags: Vec<String> = par_top_k(scores, n).into_iter().cloned().collect();
 
         file_data.top_tags = top_tags;
     });
 }
+
+/// Find the top `n` pairs of tags by the number of questions in which they appear
+/// together and by their pointwise mutual information.
+///
+/// Pairs that appear together in few questions can have a very high PMI by chance, so
+/// `min_questions` should be set to rank them by PMI. Pairs with the same value are
+/// ranked by their tags.
+///
+/// # Arguments
+///
+/// * `pairs`: The pairs of tags to rank.
+/// * `tag_counts`: The `(questions, words)` counts of the tags of the pairs.
+/// * `questions`: The total number of questions in which the pairs were counted.
+/// * `n`: The number of pairs of each ranking.
+/// * `min_questions`: The minimum number of questions of a ranked pair.
+///
+/// # Returns
+///
+/// The `TopTagPairs` with both rankings.
+///
+pub fn top_n_tag_pairs(
+    pairs: &TagPairs,
+    tag_counts: &HashMap<String, (usize, usize)>,
+    questions: usize,
+    n: usize,
+    min_questions: usize,
+) -> TopTagPairs {
+    let lift = |(tag1, tag2): &(String, String), count: usize| {
+        let tag_questions = |tag: &String| {
+            tag_counts
+                .get(tag)
+                .map_or(count, |&(tag_questions, _)| tag_questions)
+                .max(1)
+        };
+        (count * questions) as f64 / (tag_questions(tag1) * tag_questions(tag2)) as f64
+    };
+    let stats = |pair: &(String, String)| {
+        let count = pairs.count(&pair.0, &pair.1);
+        let lift = lift(pair, count);
+        TagPairStats {
+            tags: pair.clone(),
+            questions: count,
+            pmi: lift.ln(),
+            lift,
+        }
+    };
+
+    let supported = || {
+        pairs
+            .par_iter()
+            .filter(|&(_, count)| count >= min_questions)
+    };
+    let by_count = par_top_k(supported().map(|(pair, count)| (pair, count as f64)), n);
+    let by_pmi = par_top_k(
+        supported().map(|(pair, count)| (pair, lift(pair, count).ln())),
+        n,
+    );
+
+    TopTagPairs {
+        by_count: by_count.into_iter().map(stats).collect(),
+        by_pmi: by_pmi.into_iter().map(stats).collect(),
+    }
+}
+
+/// Generate the top `n` pairs of tags for each file with counted pairs.
+///
+/// # Arguments
+///
+/// * `files_data`: A mutable slice of `FileData` instances representing the file data.
+/// * `n`: The number of pairs of each ranking of a file.
+/// * `min_questions`: The minimum number of questions of a ranked pair in a file.
+///
+pub fn generate_top_n_tag_pairs(files_data: &mut [FileData], n: usize, min_questions: usize) {
+    files_data.par_iter_mut().for_each(|file_data| {
+        file_data.top_tag_pairs = (!file_data.tag_pairs.is_empty()).then(|| {
+            top_n_tag_pairs(
+                &file_data.tag_pairs,
+                &file_data.tag_counts,
+                file_data.total_line_count,
+                n,
+                min_questions,
+            )
+        });
+    });
+}
+
+/// Find the top `n` distinctive terms of every tag, compared to the other tags.
+///
+/// # Arguments
+///
+/// * `tag_terms`: The term counts of every tag, as returned by `aggregate_tag_terms`.
+/// * `n`: The number of terms to select for each tag.
+///
+/// # Returns
+///
+/// A HashMap with the top `n` terms by TF-IDF of every tag.
+///
+pub fn top_n_tag_terms(
+    tag_terms: &HashMap<String, TermCounts>,
+    n: usize,
+) -> HashMap<String, Vec<TermScore>> {
+    let (tags, terms): (Vec<&String>, Vec<&TermCounts>) = tag_terms.iter().unzip();
+    tags.into_iter()
+        .cloned()
+        .zip(top_n_terms_tf_idf(&terms, n))
+        .collect()
+}
+
+/// Generate the top `n` distinctive terms of each file, compared to the other files.
+///
+/// # Arguments
+///
+/// * `files_data`: A mutable slice of `FileData` instances representing the file data.
+/// * `n`: The number of terms to select for each file.
+///
+pub fn generate_top_n_terms(files_data: &mut [FileData], n: usize) {
+    let terms: Vec<&TermCounts> = files_data
+        .iter()
+        .map(|file_data| &file_data.terms)
+        .collect();
+    let top_terms = top_n_terms_tf_idf(&terms, n);
+    for (file_data, top_terms) in files_data.iter_mut().zip(top_terms) {
+        file_data.top_terms = top_terms;
+    }
+}
+
+/// Generate the `k` most frequent words of each file with a heavy hitter summary.
+///
+/// # Arguments
+///
+/// * `files_data`: A mutable slice of `FileData` instances representing the file data.
+/// * `k`: The number of words to select for each file.
+///
+pub fn generate_top_k_words(files_data: &mut [FileData], k: usize) {
+    files_data.par_iter_mut().for_each(|file_data| {
+        file_data.top_words = file_data
+            .heavy_hitters
+            .as_ref()
+            .map(|heavy_hitters| heavy_hitters.top_k(k));
+    });
+}
+
+/// Scores of the entries of a ranking by a metric.
+struct Scorer {
+    metric: RankingMetric,
+    /// Mean number of words per question of all the ranked entries, used by the smoothed mean.
+    prior_mean: f64,
+}
+
+impl Scorer {
+    /// Prepare the scoring, summing the `(questions, words)` of all the entries only when
+    /// the metric needs them.
+    fn new<F: FnOnce() -> (usize, usize)>(metric: RankingMetric, totals: F) -> Self {
+        let prior_mean = match metric {
+            RankingMetric::SmoothedMean { .. } => {
+                let (questions, words) = totals();
+                ratio(questions, words)
+            }
+            _ => 0.0,
+        };
+        Scorer { metric, prior_mean }
+    }
+
+    /// Score an entry with the given counts and question lengths.
+    fn score(&self, questions: usize, words: usize, lengths: Option<&LengthSketch>) -> f64 {
+        let quantile = |quantile| {
+            lengths
+                .and_then(|lengths| lengths.quantile(quantile))
+                .unwrap_or(0.0)
+        };
+        match self.metric {
+            RankingMetric::Mean => ratio(questions, words),
+            RankingMetric::Median => quantile(0.5),
+            RankingMetric::P90 => quantile(0.9),
+            RankingMetric::SmoothedMean { prior_questions } => {
+                let weight = (prior_questions + questions) as f64;
+                if weight > 0.0 {
+                    (prior_questions as f64 * self.prior_mean + words as f64) / weight
+                } else {
+                    0.0
+                }
+            }
+            RankingMetric::TotalWords => words as f64,
+            RankingMetric::Questions => questions as f64,
+        }
+    }
+}
+
+/// Sum `(questions, words)` counts in parallel.
+fn sum_counts<I: ParallelIterator<Item = (usize, usize)>>(counts: I) -> (usize, usize) {
+    counts.reduce(
+        || (0, 0),
+        |(questions1, words1), (questions2, words2)| (questions1 + questions2, words1 + words2),
+    )
+}
+
+/// Ratio of words to questions, zero when there are no questions.
+pub(crate) fn ratio(questions: usize, words: usize) -> f64 {
+    if questions > 0 {
+        words as f64 / questions as f64
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,651 @@
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tp1::{
+    CodeBlocks, ErrorPolicy, MarkupTokenizer, MinHashOptions, Normalization, OutputOptions,
+    ProcessOptions, RankingMetric, RankingOptions, Schema, TagFormat, TextRole, Tokenizer,
+    UnicodeWordTokenizer, WhitespaceTokenizer, DEFAULT_DISTINCT_PRECISION,
+    DEFAULT_DUPLICATE_THRESHOLD, DEFAULT_HEAVY_HITTER_CAPACITY, DEFAULT_MAX_PAIR_TAGS,
+    DEFAULT_MAX_TERMS, DEFAULT_MINHASH_BANDS, DEFAULT_MINHASH_ROWS, DEFAULT_PRIOR_QUESTIONS,
+    DEFAULT_SHINGLE_WORDS, DEFAULT_TOP, DISTINCT_PRECISION_RANGE, ENGLISH_STOPWORDS,
+    SPANISH_STOPWORDS,
+};
+
+/// Command-line arguments of the program.
+///
+/// Without a subcommand, the inputs are processed. Each input can be either a
+/// `.jsonl` file (optionally compressed as `.jsonl.gz`, `.jsonl.zst` or `.jsonl.bz2`)
+/// or a directory whose JSONL files are processed. When no input is given, the
+/// `data` directory is used.
+///
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Compute word and tag statistics over StackExchange JSONL dumps",
+    long_about = None,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub process: ProcessArgs,
+}
+
+/// Subcommands of the program.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Combine the results of independently processed shards into one result.
+    Merge(MergeArgs),
+    /// Compare two results and report the changes in counts and rankings.
+    Diff(DiffArgs),
+}
+
+/// Arguments used to process the input files.
+#[derive(Debug, Args)]
+#[command(group(
+    ArgGroup::new("duplicate_detection")
+        .multiple(true)
+        .args(["duplicates", "exclude_duplicates"])
+))]
+pub struct ProcessArgs {
+    /// Files or directories to process; directories are expanded to the files they contain.
+    #[arg(value_name = "INPUT", default_value = "data")]
+    pub inputs: Vec<PathBuf>,
+
+    /// What to do with lines that are not valid questions.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OnError::Fail)]
+    pub on_error: OnError,
+
+    /// JSON pointer to a field with the texts of a question, a string or an array; repeat
+    /// it to read several fields in order.
+    #[arg(
+        long = "text-field",
+        value_name = "POINTER",
+        default_value = "/texts",
+        value_parser = parse_pointer
+    )]
+    pub text_fields: Vec<String>,
+
+    /// JSON pointer to the field with the tags of a question, an array of tags or a string,
+    /// which is a single tag unless --tag-delimiters or --tag-separator is given.
+    #[arg(long, value_name = "POINTER", default_value = "/tags", value_parser = parse_pointer)]
+    pub tag_field: String,
+
+    /// Treat questions whose text or tag fields are missing or have another type as
+    /// invalid, instead of counting them without those texts or tags.
+    #[arg(long)]
+    pub require_fields: bool,
+
+    /// Read a string tag field as tags enclosed in these two characters, such as "<>".
+    #[arg(long, value_name = "OPEN_CLOSE", value_parser = parse_delimiters)]
+    pub tag_delimiters: Option<(char, char)>,
+
+    /// Read a string tag field as tags separated by this character.
+    #[arg(long, value_name = "CHAR", conflicts_with = "tag_delimiters")]
+    pub tag_separator: Option<char>,
+
+    /// Report the min, max, median, p90 and p99 words per question of every site and tag.
+    #[arg(long)]
+    pub lengths: bool,
+
+    /// Break the words of every site and tag down into title, body and other texts.
+    #[arg(long)]
+    pub texts: bool,
+
+    /// Role of each entry of `texts`, in order; later entries are `other`.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ROLE",
+        value_delimiter = ',',
+        default_value = "title,body",
+        requires = "texts"
+    )]
+    pub text_roles: Vec<Role>,
+
+    /// Count the questions in which every pair of tags appears together and rank the pairs.
+    #[arg(long)]
+    pub tag_pairs: bool,
+
+    /// Maximum number of tags of a question whose pairs are counted.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_PAIR_TAGS,
+        value_parser = parse_max_pair_tags,
+        requires = "tag_pairs"
+    )]
+    pub max_pair_tags: usize,
+
+    /// Count the terms of every site and tag and report their distinctive terms by TF-IDF.
+    #[arg(long)]
+    pub terms: bool,
+
+    /// Built-in stopword lists left out of the terms.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "LIST",
+        value_delimiter = ',',
+        default_value = "english,spanish",
+        requires = "terms"
+    )]
+    pub stopwords: Vec<Stopwords>,
+
+    /// Additional word left out of the terms; can be repeated.
+    #[arg(long = "stopword", value_name = "WORD", requires = "terms")]
+    pub extra_stopwords: Vec<String>,
+
+    /// Maximum number of distinct terms kept for each site and tag; 0 keeps them all.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_TERMS, requires = "terms")]
+    pub max_terms: usize,
+
+    /// Estimate the distinct words and lexical diversity of every site and tag.
+    #[arg(long)]
+    pub distinct_words: bool,
+
+    /// Precision of the distinct word estimates; each sketch takes 2^P bytes.
+    #[arg(
+        long,
+        value_name = "P",
+        default_value_t = DEFAULT_DISTINCT_PRECISION,
+        value_parser = parse_distinct_precision,
+        requires = "distinct_words"
+    )]
+    pub distinct_precision: u8,
+
+    /// Report the most frequent words of every site and across all sites.
+    #[arg(long)]
+    pub heavy_hitters: bool,
+
+    /// Counters of every frequent word summary; counts are at most words / (N + 1) too low.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_HEAVY_HITTER_CAPACITY,
+        value_parser = parse_heavy_hitter_capacity,
+        requires = "heavy_hitters"
+    )]
+    pub heavy_hitter_capacity: usize,
+
+    /// Find the near-duplicate questions within and across sites with MinHash.
+    #[arg(long)]
+    pub duplicates: bool,
+
+    /// Leave the near-duplicate questions out of the counts, keeping the first of each
+    /// cluster; implies --duplicates.
+    #[arg(long)]
+    pub exclude_duplicates: bool,
+
+    /// Minimum estimated Jaccard similarity of the words of two near-duplicates.
+    #[arg(
+        long,
+        value_name = "S",
+        default_value_t = DEFAULT_DUPLICATE_THRESHOLD,
+        value_parser = parse_similarity,
+        requires = "duplicate_detection"
+    )]
+    pub duplicate_threshold: f64,
+
+    /// Number of consecutive words of every shingle compared between questions.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_SHINGLE_WORDS,
+        value_parser = parse_minhash_size,
+        requires = "duplicate_detection"
+    )]
+    pub shingle_words: usize,
+
+    /// Number of bands of every MinHash signature.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MINHASH_BANDS,
+        value_parser = parse_minhash_size,
+        requires = "duplicate_detection"
+    )]
+    pub minhash_bands: usize,
+
+    /// Number of hashes of every band of a MinHash signature.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MINHASH_ROWS,
+        value_parser = parse_minhash_size,
+        requires = "duplicate_detection"
+    )]
+    pub minhash_rows: usize,
+
+    /// Remove HTML tags and replace HTML entities before counting words.
+    #[arg(long)]
+    pub strip_html: bool,
+
+    /// What to do with the code blocks of the texts.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = Code::Keep)]
+    pub code_blocks: Code,
+
+    /// Count every URL as a single word.
+    #[arg(long)]
+    pub collapse_urls: bool,
+
+    /// How the texts are split into words.
+    #[arg(long, value_enum, default_value_t = WordTokenizer::Whitespace)]
+    pub tokenizer: WordTokenizer,
+
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+impl ProcessArgs {
+    /// Build the options used to process the input files.
+    pub fn process_options(&self) -> ProcessOptions {
+        ProcessOptions {
+            on_error: self.on_error.into(),
+            schema: self.schema(),
+            require_fields: self.require_fields,
+            collect_lengths: self.lengths || self.common.ranking_options().metric.needs_lengths(),
+            collect_texts: self.texts,
+            text_roles: self.text_roles.iter().map(|&role| role.into()).collect(),
+            collect_tag_pairs: self.tag_pairs,
+            max_pair_tags: self.max_pair_tags,
+            collect_terms: self.terms,
+            stopwords: self
+                .stopwords
+                .iter()
+                .flat_map(|&list| match list {
+                    Stopwords::English => ENGLISH_STOPWORDS,
+                    Stopwords::Spanish => SPANISH_STOPWORDS,
+                })
+                .map(|word| word.to_string())
+                .chain(self.extra_stopwords.iter().map(|word| word.to_lowercase()))
+                .collect(),
+            max_terms: self.max_terms,
+            collect_distinct_words: self.distinct_words,
+            distinct_precision: self.distinct_precision,
+            collect_heavy_hitters: self.heavy_hitters,
+            heavy_hitter_capacity: self.heavy_hitter_capacity,
+            detect_duplicates: self.duplicates || self.exclude_duplicates,
+            minhash: MinHashOptions {
+                shingle_words: self.shingle_words,
+                bands: self.minhash_bands,
+                rows: self.minhash_rows,
+            },
+            duplicate_threshold: self.duplicate_threshold,
+            exclude_duplicates: self.exclude_duplicates,
+            normalization: Normalization {
+                strip_html: self.strip_html,
+                code_blocks: self.code_blocks.into(),
+                collapse_urls: self.collapse_urls,
+            },
+            tokenizer: self.tokenizer.into(),
+            ..ProcessOptions::default()
+        }
+    }
+
+    /// Build the schema used to read the texts and tags of every line.
+    fn schema(&self) -> Schema {
+        let tag_format = match (self.tag_delimiters, self.tag_separator) {
+            (Some((open, close)), _) => TagFormat::Enclosed { open, close },
+            (None, Some(separator)) => TagFormat::Separated(separator),
+            (None, None) => TagFormat::Single,
+        };
+        Schema {
+            text_pointers: self.text_fields.clone(),
+            tag_pointer: self.tag_field.clone(),
+            tag_format,
+        }
+    }
+}
+
+/// Arguments of the `merge` subcommand.
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// JSON results to merge, as written by a previous run.
+    #[arg(value_name = "RESULT", required = true)]
+    pub results: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+/// Arguments of the `diff` subcommand.
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// JSON result taken as the reference.
+    #[arg(value_name = "OLD")]
+    pub old: PathBuf,
+
+    /// JSON result compared against the reference.
+    #[arg(value_name = "NEW")]
+    pub new: PathBuf,
+
+    /// File where the diff is written instead of the standard output.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Format of the diff.
+    #[arg(short = 'f', long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// Arguments shared by every way of producing a result.
+#[derive(Debug, Args)]
+pub struct CommonArgs {
+    /// Number of worker threads.
+    #[arg(short = 't', long, default_value_t = 1, value_parser = parse_threads)]
+    pub threads: usize,
+
+    /// Number of entries of every ranking not given a size of its own.
+    #[arg(short = 'n', long, default_value_t = DEFAULT_TOP, value_parser = parse_top)]
+    pub top: usize,
+
+    /// Number of chatty sites [default: --top].
+    #[arg(long, value_name = "N", value_parser = parse_top)]
+    pub top_sites: Option<usize>,
+
+    /// Number of chatty tags across all sites [default: --top].
+    #[arg(long, value_name = "N", value_parser = parse_top)]
+    pub top_tags: Option<usize>,
+
+    /// Number of chatty tags of each site [default: --top].
+    #[arg(long, value_name = "N", value_parser = parse_top)]
+    pub top_site_tags: Option<usize>,
+
+    /// Minimum number of questions of the entries of every ranking not given a minimum of its own.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub min_questions: usize,
+
+    /// Minimum number of questions of a chatty site [default: --min-questions].
+    #[arg(long, value_name = "N")]
+    pub min_questions_sites: Option<usize>,
+
+    /// Minimum number of questions of a chatty tag across all sites [default: --min-questions].
+    #[arg(long, value_name = "N")]
+    pub min_questions_tags: Option<usize>,
+
+    /// Minimum number of questions of a chatty tag in its site [default: --min-questions].
+    #[arg(long, value_name = "N")]
+    pub min_questions_site_tags: Option<usize>,
+
+    /// Number of top pairs of tags, across all sites and of each site [default: --top].
+    #[arg(long, value_name = "N", value_parser = parse_top)]
+    pub top_tag_pairs: Option<usize>,
+
+    /// Minimum number of questions of a top pair of tags [default: --min-questions].
+    #[arg(long, value_name = "N")]
+    pub min_questions_tag_pairs: Option<usize>,
+
+    /// Number of distinctive terms of every site and tag [default: --top].
+    #[arg(long, value_name = "N", value_parser = parse_top)]
+    pub top_terms: Option<usize>,
+
+    /// Number of most frequent words, across all sites and of each site [default: --top].
+    #[arg(long, value_name = "N", value_parser = parse_top)]
+    pub top_words: Option<usize>,
+
+    /// Metric by which sites and tags are ranked.
+    #[arg(short = 'm', long, value_enum, default_value_t = Metric::Mean)]
+    pub metric: Metric,
+
+    /// Questions of average length added to every entry by the smoothed-mean metric.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_PRIOR_QUESTIONS)]
+    pub prior_questions: usize,
+
+    /// File where the result is written instead of the standard output.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Format of the result.
+    #[arg(short = 'f', long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+
+    /// Write sites by filename and keys in alphabetical order, for reproducible output.
+    #[arg(long)]
+    pub sort_keys: bool,
+
+    /// Keep in the result the state needed to merge it with other results later.
+    #[arg(long)]
+    pub state: bool,
+}
+
+impl CommonArgs {
+    /// Build the sizes and minimum support of the rankings of the result.
+    pub fn ranking_options(&self) -> RankingOptions {
+        RankingOptions {
+            top_sites: self.top_sites.unwrap_or(self.top),
+            top_tags: self.top_tags.unwrap_or(self.top),
+            top_site_tags: self.top_site_tags.unwrap_or(self.top),
+            min_questions_sites: self.min_questions_sites.unwrap_or(self.min_questions),
+            min_questions_tags: self.min_questions_tags.unwrap_or(self.min_questions),
+            min_questions_site_tags: self.min_questions_site_tags.unwrap_or(self.min_questions),
+            top_tag_pairs: self.top_tag_pairs.unwrap_or(self.top),
+            min_questions_tag_pairs: self.min_questions_tag_pairs.unwrap_or(self.min_questions),
+            top_terms: self.top_terms.unwrap_or(self.top),
+            top_words: self.top_words.unwrap_or(self.top),
+            metric: match self.metric {
+                Metric::Mean => RankingMetric::Mean,
+                Metric::Median => RankingMetric::Median,
+                Metric::P90 => RankingMetric::P90,
+                Metric::SmoothedMean => RankingMetric::SmoothedMean {
+                    prior_questions: self.prior_questions,
+                },
+                Metric::TotalWords => RankingMetric::TotalWords,
+                Metric::Questions => RankingMetric::Questions,
+            },
+        }
+    }
+
+    /// Build the options used to write the result.
+    pub fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            sort_keys: self.sort_keys,
+        }
+    }
+}
+
+/// Formats in which the result can be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// JSON document.
+    Json,
+    /// Human-readable summary.
+    Text,
+}
+
+/// Metrics by which sites and tags can be ranked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    /// Mean words per question.
+    Mean,
+    /// Median words per question.
+    Median,
+    /// 90th percentile of words per question.
+    P90,
+    /// Mean words per question shrunk toward the average of all entries.
+    SmoothedMean,
+    /// Total words.
+    TotalWords,
+    /// Number of questions.
+    Questions,
+}
+
+/// Roles of the entries of `texts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Role {
+    /// Title of the question.
+    Title,
+    /// Body of the question.
+    Body,
+    /// Any other text.
+    Other,
+}
+
+impl From<Role> for TextRole {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Title => TextRole::Title,
+            Role::Body => TextRole::Body,
+            Role::Other => TextRole::Other,
+        }
+    }
+}
+
+/// Policies for lines that are not valid questions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnError {
+    /// Stop at the first invalid line.
+    Fail,
+    /// Skip invalid lines and report them in the result.
+    Skip,
+}
+
+impl From<OnError> for ErrorPolicy {
+    fn from(on_error: OnError) -> Self {
+        match on_error {
+            OnError::Fail => ErrorPolicy::Fail,
+            OnError::Skip => ErrorPolicy::Skip,
+        }
+    }
+}
+
+/// What can be done with the code blocks of the texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Code {
+    /// Count their words like any other.
+    Keep,
+    /// Leave their words out.
+    Exclude,
+    /// Count their words separately, as code words.
+    Separate,
+}
+
+impl From<Code> for CodeBlocks {
+    fn from(code: Code) -> Self {
+        match code {
+            Code::Keep => CodeBlocks::Keep,
+            Code::Exclude => CodeBlocks::Exclude,
+            Code::Separate => CodeBlocks::Separate,
+        }
+    }
+}
+
+/// Built-in lists of stopwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Stopwords {
+    /// Common English words.
+    English,
+    /// Common Spanish words.
+    Spanish,
+}
+
+/// Ways of splitting the texts into words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WordTokenizer {
+    /// Split at whitespace.
+    Whitespace,
+    /// Split at Unicode word boundaries, leaving out punctuation.
+    Unicode,
+    /// Leave out HTML tags and entities, then split at Unicode word boundaries.
+    Markup,
+}
+
+impl From<WordTokenizer> for Arc<dyn Tokenizer> {
+    fn from(tokenizer: WordTokenizer) -> Self {
+        match tokenizer {
+            WordTokenizer::Whitespace => Arc::new(WhitespaceTokenizer),
+            WordTokenizer::Unicode => Arc::new(UnicodeWordTokenizer),
+            WordTokenizer::Markup => Arc::new(MarkupTokenizer),
+        }
+    }
+}
+
+/// Parse the thread count, rejecting zero.
+fn parse_threads(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("the thread count must be at least 1".to_string()),
+        Ok(threads) => Ok(threads),
+        Err(err) => Err(format!("invalid thread count: {}", err)),
+    }
+}
+
+/// Parse the ranking size, rejecting zero.
+fn parse_top(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("the ranking size must be at least 1".to_string()),
+        Ok(top) => Ok(top),
+        Err(err) => Err(format!("invalid ranking size: {}", err)),
+    }
+}
+
+/// Parse the number of counters of the heavy hitter summaries, rejecting zero.
+fn parse_heavy_hitter_capacity(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("the heavy hitter capacity must be at least 1".to_string()),
+        Ok(capacity) => Ok(capacity),
+        Err(err) => Err(format!("invalid heavy hitter capacity: {}", err)),
+    }
+}
+
+/// Parse the maximum number of paired tags, rejecting fewer than two.
+fn parse_max_pair_tags(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0 | 1) => Err("at least two tags must be paired".to_string()),
+        Ok(max_tags) => Ok(max_tags),
+        Err(err) => Err(format!("invalid number of tags: {}", err)),
+    }
+}
+
+/// Parse the precision of the distinct word sketches.
+fn parse_distinct_precision(value: &str) -> Result<u8, String> {
+    match value.parse::<u8>() {
+        Ok(precision) if DISTINCT_PRECISION_RANGE.contains(&precision) => Ok(precision),
+        Ok(_) => Err(format!(
+            "the precision must be between {} and {}",
+            DISTINCT_PRECISION_RANGE.start(),
+            DISTINCT_PRECISION_RANGE.end()
+        )),
+        Err(err) => Err(format!("invalid precision: {}", err)),
+    }
+}
+
+/// Parse a similarity, which is between 0 and 1.
+fn parse_similarity(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(similarity) if (0.0..=1.0).contains(&similarity) => Ok(similarity),
+        Ok(_) => Err("the similarity must be between 0 and 1".to_string()),
+        Err(err) => Err(format!("invalid similarity: {}", err)),
+    }
+}
+
+/// Parse a size of the shingles or MinHash signatures, rejecting zero.
+fn parse_minhash_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("the size must be at least 1".to_string()),
+        Ok(size) => Ok(size),
+        Err(err) => Err(format!("invalid size: {}", err)),
+    }
+}
+
+/// Parse a JSON pointer, which is empty or starts with a slash.
+fn parse_pointer(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.starts_with('/') {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "a JSON pointer must start with '/', as in \"/{}\"",
+            value
+        ))
+    }
+}
+
+/// Parse a pair of tag delimiters, given as two characters.
+fn parse_delimiters(value: &str) -> Result<(char, char), String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(open), Some(close), None) => Ok((open, close)),
+        _ => Err("the tag delimiters must be exactly two characters, such as \"<>\"".to_string()),
+    }
+}
@@ -0,0 +1,180 @@
+use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Default maximum number of tags of a question whose pairs are counted.
+///
+/// StackExchange questions have at most five tags, so the default only bounds the
+/// pairs of other datasets.
+///
+pub const DEFAULT_MAX_PAIR_TAGS: usize = 10;
+
+/// Struct representing the number of questions in which every pair of tags appears together.
+///
+/// Only the pairs that appear in some question are kept, and each pair is kept once, with
+/// its tags in alphabetical order. Only the first `max_tags` distinct tags of a question are
+/// paired, so a question adds at most `max_tags * (max_tags - 1) / 2` pairs.
+///
+/// In JSON, the pairs are a list of objects with the `tags` of the pair and the number of
+/// `questions` in which they appear together, sorted by tags.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TagPairs {
+    counts: HashMap<(String, String), usize>,
+}
+
+impl TagPairs {
+    /// Count the pairs of tags of a question.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags`: The tags of the question. Repeated tags are counted once.
+    /// * `max_tags`: The maximum number of distinct tags that are paired, taken in order.
+    ///
+    pub fn record(&mut self, tags: &[&str], max_tags: usize) {
+        let mut distinct: Vec<&str> = Vec::with_capacity(tags.len().min(max_tags));
+        for &tag in tags {
+            if distinct.len() == max_tags {
+                break;
+            }
+            if !distinct.contains(&tag) {
+                distinct.push(tag);
+            }
+        }
+        distinct.sort_unstable();
+
+        for (position, &first) in distinct.iter().enumerate() {
+            for &second in &distinct[position + 1..] {
+                *self
+                    .counts
+                    .entry((first.to_string(), second.to_string()))
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Add the pairs of another `TagPairs` to this one.
+    pub fn merge(&mut self, other: &TagPairs) {
+        for (pair, &count) in &other.counts {
+            *self.counts.entry(pair.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Number of questions in which two tags appear together, in any order.
+    pub fn count(&self, tag1: &str, tag2: &str) -> usize {
+        let pair = if tag1 <= tag2 {
+            (tag1.to_string(), tag2.to_string())
+        } else {
+            (tag2.to_string(), tag1.to_string())
+        };
+        self.counts.get(&pair).copied().unwrap_or(0)
+    }
+
+    /// Number of distinct pairs.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Whether no pair was counted.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Iterate over the pairs, with their tags in alphabetical order, and their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (&(String, String), usize)> {
+        self.counts.iter().map(|(pair, &count)| (pair, count))
+    }
+
+    /// Iterate in parallel over the pairs and their counts.
+    pub(crate) fn par_iter(
+        &self,
+    ) -> impl rayon::iter::ParallelIterator<Item = (&(String, String), usize)> {
+        use rayon::prelude::*;
+        self.counts.par_iter().map(|(pair, &count)| (pair, count))
+    }
+}
+
+/// Struct representing a pair of tags in a ranking.
+///
+/// The pointwise mutual information (PMI) of a pair is the logarithm of its lift, which
+/// is how many times more often the tags appear together than they would if they were
+/// independent: `questions * total questions / (questions of tag 1 * questions of tag 2)`.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagPairStats {
+    pub tags: (String, String),
+    pub questions: usize,
+    pub pmi: f64,
+    pub lift: f64,
+}
+
+/// Struct representing the top pairs of tags of a set of questions.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopTagPairs {
+    /// Pairs that appear together in the most questions.
+    pub by_count: Vec<TagPairStats>,
+    /// Pairs that appear together the most often compared to how often each tag appears.
+    pub by_pmi: Vec<TagPairStats>,
+}
+
+impl Serialize for TagPairs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct StoredPair<'a> {
+            tags: (&'a str, &'a str),
+            questions: usize,
+        }
+
+        let mut pairs: Vec<(&(String, String), usize)> = self.iter().collect();
+        pairs.sort_unstable();
+        let mut seq = serializer.serialize_seq(Some(pairs.len()))?;
+        for ((tag1, tag2), questions) in pairs {
+            seq.serialize_element(&StoredPair {
+                tags: (tag1, tag2),
+                questions,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for TagPairs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(TagPairsVisitor)
+    }
+}
+
+struct TagPairsVisitor;
+
+impl<'de> Visitor<'de> for TagPairsVisitor {
+    type Value = TagPairs;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of tag pairs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<TagPairs, A::Error> {
+        #[derive(Deserialize)]
+        struct StoredPair {
+            tags: (String, String),
+            questions: usize,
+        }
+
+        let mut pairs = TagPairs::default();
+        while let Some(StoredPair {
+            tags: (tag1, tag2),
+            questions,
+        }) = seq.next_element()?
+        {
+            let pair = if tag1 <= tag2 {
+                (tag1, tag2)
+            } else {
+                (tag2, tag1)
+            };
+            *pairs.counts.entry(pair).or_insert(0) += questions;
+        }
+        Ok(pairs)
+    }
+}
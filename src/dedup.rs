@@ -0,0 +1,271 @@
+use crate::file_data::FileData;
+use crate::sketch::{mix, DistinctSketch};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Default number of consecutive words of every shingle.
+pub const DEFAULT_SHINGLE_WORDS: usize = 3;
+
+/// Default number of bands in which every MinHash signature is split.
+pub const DEFAULT_MINHASH_BANDS: usize = 16;
+
+/// Default number of hashes of every band of a MinHash signature.
+pub const DEFAULT_MINHASH_ROWS: usize = 4;
+
+/// Default estimated Jaccard similarity above which two questions are near-duplicates.
+pub const DEFAULT_DUPLICATE_THRESHOLD: f64 = 0.8;
+
+/// Maximum number of clusters listed in the text summary of a result.
+pub const DUPLICATE_CLUSTER_SAMPLE_SIZE: usize = 10;
+
+/// How the MinHash signatures of the questions are computed and compared.
+///
+/// Every signature has `bands * rows` hashes. Two questions are compared only when all
+/// the hashes of one of their bands are equal, which happens with probability
+/// `1 - (1 - s^rows)^bands` for questions of similarity `s`: the defaults find almost
+/// every pair above 0.8 and few pairs below 0.4.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinHashOptions {
+    /// Number of consecutive lowercase words of every shingle. Questions with fewer words
+    /// have a single shingle with all of them.
+    pub shingle_words: usize,
+    pub bands: usize,
+    pub rows: usize,
+}
+
+impl Default for MinHashOptions {
+    fn default() -> Self {
+        MinHashOptions {
+            shingle_words: DEFAULT_SHINGLE_WORDS,
+            bands: DEFAULT_MINHASH_BANDS,
+            rows: DEFAULT_MINHASH_ROWS,
+        }
+    }
+}
+
+impl MinHashOptions {
+    /// Number of hashes of every signature.
+    pub fn hashes(&self) -> usize {
+        self.bands.max(1) * self.rows.max(1)
+    }
+}
+
+/// Struct representing the MinHash signature of a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuestionSignature {
+    /// Number of the line of the question within its file.
+    pub line: usize,
+    pub hashes: Vec<u32>,
+}
+
+impl QuestionSignature {
+    /// Compute the signature of the words of a question.
+    ///
+    /// # Arguments
+    ///
+    /// * `line`: The number of the line of the question.
+    /// * `words`: The lowercase words of the question, in order.
+    /// * `options`: The size of the shingles and of the signature.
+    ///
+    /// # Returns
+    ///
+    /// The signature, or `None` if the question has no words.
+    ///
+    pub fn new(line: usize, words: &[String], options: &MinHashOptions) -> Option<Self> {
+        if words.is_empty() {
+            return None;
+        }
+        let word_hashes: Vec<u64> = words
+            .iter()
+            .map(|word| DistinctSketch::hash_word(word))
+            .collect();
+        let shingle_words = options.shingle_words.clamp(1, word_hashes.len());
+        let shingles: Vec<u64> = word_hashes
+            .windows(shingle_words)
+            .map(|window| {
+                window
+                    .iter()
+                    .fold(0, |hash: u64, &word| mix(hash.rotate_left(21) ^ word))
+            })
+            .collect();
+
+        let hashes = (0..options.hashes())
+            .map(|index| {
+                let seed = mix((index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+                shingles
+                    .iter()
+                    .map(|&shingle| (mix(shingle ^ seed) >> 32) as u32)
+                    .min()
+                    .unwrap_or(u32::MAX)
+            })
+            .collect();
+        Some(QuestionSignature { line, hashes })
+    }
+
+    /// Estimate the Jaccard similarity of the shingles of two questions, as the share of
+    /// equal hashes of their signatures.
+    pub fn similarity(&self, other: &QuestionSignature) -> f64 {
+        let hashes = self.hashes.len().min(other.hashes.len());
+        if hashes == 0 {
+            return 0.0;
+        }
+        let equal = self
+            .hashes
+            .iter()
+            .zip(&other.hashes)
+            .filter(|(hash1, hash2)| hash1 == hash2)
+            .count();
+        equal as f64 / hashes as f64
+    }
+}
+
+/// Struct representing a question by its site and line.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct QuestionRef {
+    pub site: String,
+    pub line: usize,
+}
+
+/// Struct representing a question that is a near-duplicate of another one.
+///
+/// Every cluster of near-duplicates keeps its first question, in the order in which the
+/// sites were processed and by line, and the rest that are similar enough to it are
+/// duplicates of it.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Duplicate {
+    /// Number of the line of the duplicate within its site.
+    pub line: usize,
+    /// The question that is kept.
+    pub of: QuestionRef,
+    /// Estimated Jaccard similarity of both questions.
+    pub similarity: f64,
+}
+
+/// Struct representing a set of near-duplicate questions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateCluster {
+    /// The questions of the cluster. The first one is the one kept, and the rest are in the
+    /// order of their sites and lines.
+    pub questions: Vec<QuestionRef>,
+    /// Whether the questions belong to more than one site.
+    pub across_sites: bool,
+}
+
+/// Find the near-duplicate questions of a set of sites.
+///
+/// The signatures are split into bands, and every pair of questions that shares a band is
+/// a candidate. The bands are bucketed one at a time, so besides the signatures only one
+/// key per question is held at once. Each question is compared with the first question
+/// of every bucket it falls in, and the pairs whose estimated similarity is at least
+/// `threshold` are joined into clusters, within and across sites.
+///
+/// Joining pairs chains questions that are not similar themselves, so a question of a
+/// cluster is only a duplicate if it is also similar enough to the first question of the
+/// cluster, which is the one kept; otherwise it is kept as well.
+///
+/// # Arguments
+///
+/// * `sites`: The sites whose `signatures` are compared.
+/// * `options`: The bands and rows of the signatures.
+/// * `threshold`: The minimum estimated similarity of a pair of near-duplicates.
+///
+/// # Returns
+///
+/// The duplicates of every site, in the order of `sites` and by line.
+///
+pub fn find_duplicates(
+    sites: &[FileData],
+    options: &MinHashOptions,
+    threshold: f64,
+) -> Vec<Vec<Duplicate>> {
+    let questions: Vec<(usize, &QuestionSignature)> = sites
+        .iter()
+        .enumerate()
+        .flat_map(|(site, file)| {
+            file.signatures
+                .iter()
+                .map(move |signature| (site, signature))
+        })
+        .collect();
+
+    let rows = options.rows.max(1);
+    let mut parents: Vec<usize> = (0..questions.len()).collect();
+    for band in 0..options.bands.max(1) {
+        let mut buckets: Vec<(u64, usize)> = questions
+            .par_iter()
+            .enumerate()
+            .filter_map(|(question, (_, signature))| {
+                let hashes = signature.hashes.get(band * rows..(band + 1) * rows)?;
+                let key = hashes
+                    .iter()
+                    .fold(band as u64, |hash, &row| mix(hash ^ row as u64));
+                Some((key, question))
+            })
+            .collect();
+        buckets.par_sort_unstable();
+
+        let pairs: Vec<(usize, usize)> = buckets
+            .par_chunk_by(|(key1, _), (key2, _)| key1 == key2)
+            .flat_map_iter(|bucket| {
+                let first = bucket[0].1;
+                bucket[1..]
+                    .iter()
+                    .map(move |&(_, question)| (first, question))
+            })
+            .filter(|&(question1, question2)| {
+                questions[question1].1.similarity(questions[question2].1) >= threshold
+            })
+            .collect();
+        for (question1, question2) in pairs {
+            let root1 = find_root(&mut parents, question1);
+            let root2 = find_root(&mut parents, question2);
+            parents[root1.max(root2)] = root1.min(root2);
+        }
+    }
+
+    // Questions are ordered by site and line, so the root of every cluster, which is
+    // its smallest question, is the one kept.
+    let mut duplicates: Vec<Vec<Duplicate>> = vec![Vec::new(); sites.len()];
+    for question in 0..questions.len() {
+        let root = find_root(&mut parents, question);
+        if root == question {
+            continue;
+        }
+        let (site, signature) = questions[question];
+        let (kept_site, kept) = questions[root];
+        let similarity = signature.similarity(kept);
+        if similarity < threshold {
+            continue;
+        }
+        duplicates[site].push(Duplicate {
+            line: signature.line,
+            of: QuestionRef {
+                site: sites[kept_site].filename.clone(),
+                line: kept.line,
+            },
+            similarity,
+        });
+    }
+    duplicates
+}
+
+/// Find the root of the set of a question, halving the path to it.
+fn find_root(parents: &mut [usize], mut question: usize) -> usize {
+    while parents[question] != question {
+        parents[question] = parents[parents[question]];
+        question = parents[question];
+    }
+    question
+}
+
+/// Collect the lines of the duplicates of every site, which are left out of the counts
+/// when duplicates are excluded, in the order of the sites.
+pub fn duplicate_lines(duplicates: &[Vec<Duplicate>]) -> Vec<BTreeSet<usize>> {
+    duplicates
+        .iter()
+        .map(|duplicates| duplicates.iter().map(|duplicate| duplicate.line).collect())
+        .collect()
+}
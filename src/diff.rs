@@ -0,0 +1,275 @@
+use crate::analysis::ratio;
+use crate::result::ResultJson;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+
+/// Struct representing the differences between two results.
+///
+/// This struct describes how the counts of the sites and tags changed from an old
+/// result to a new one, and how the rankings of chatty sites and tags moved.
+/// Every list is sorted, so comparing the same results always gives the same diff.
+///
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ResultDiff {
+    pub sites: EntriesDiff,
+    pub tags: EntriesDiff,
+    pub chatty_sites: Vec<RankChange>,
+    pub chatty_tags: Vec<RankChange>,
+}
+
+/// Struct representing the differences between two sets of sites or tags.
+///
+/// `added` and `removed` hold the names only present in the new or in the old result,
+/// and `changed` the entries present in both whose counts differ, sorted by name.
+///
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct EntriesDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<CountsChange>,
+}
+
+/// Struct representing the change in the counts of a site or tag.
+///
+/// Deltas are the new value minus the old one.
+///
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CountsChange {
+    pub name: String,
+    pub old_questions: usize,
+    pub new_questions: usize,
+    pub questions_delta: i64,
+    pub old_words: usize,
+    pub new_words: usize,
+    pub words_delta: i64,
+    pub old_ratio: f64,
+    pub new_ratio: f64,
+    pub ratio_delta: f64,
+}
+
+/// Struct representing the change in the position of a site or tag in a ranking.
+///
+/// Ranks start at 1. A missing rank means that the entry is not in that ranking.
+///
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RankChange {
+    pub name: String,
+    pub old_rank: Option<usize>,
+    pub new_rank: Option<usize>,
+}
+
+impl ResultDiff {
+    /// Compare two results.
+    ///
+    /// # Arguments
+    ///
+    /// * `old`: The result taken as the reference.
+    /// * `new`: The result compared against the reference.
+    ///
+    /// # Returns
+    ///
+    /// The `ResultDiff` describing how `new` differs from `old`.
+    ///
+    pub fn between(old: &ResultJson, new: &ResultJson) -> Self {
+        let site_counts = |result: &ResultJson| -> HashMap<String, (usize, usize)> {
+            result
+                .sites
+                .iter()
+                .map(|site| {
+                    (
+                        site.filename.clone(),
+                        (site.total_line_count, site.total_word_count),
+                    )
+                })
+                .collect()
+        };
+
+        ResultDiff {
+            sites: diff_counts(&site_counts(old), &site_counts(new)),
+            tags: diff_counts(&old.tags, &new.tags),
+            chatty_sites: diff_ranks(&old.totals.chatty_sites, &new.totals.chatty_sites),
+            chatty_tags: diff_ranks(&old.totals.chatty_tags, &new.totals.chatty_tags),
+        }
+    }
+
+    /// Whether both results have the same counts and rankings.
+    pub fn is_empty(&self) -> bool {
+        self.sites == EntriesDiff::default()
+            && self.tags == EntriesDiff::default()
+            && self.chatty_sites.is_empty()
+            && self.chatty_tags.is_empty()
+    }
+
+    /// Write the JSON representation of the diff.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer where the JSON document is written.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the document could be written.
+    ///
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)
+    }
+
+    /// Write the diff as human-readable tables.
+    ///
+    /// This function writes the added and removed sites and tags, a table with the
+    /// counts that changed and the moves in the rankings of chatty sites and tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer where the tables are written.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the tables could be written.
+    ///
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.is_empty() {
+            return writeln!(writer, "No differences.");
+        }
+        write_entries_text(writer, "Sites", "Site", &self.sites)?;
+        write_entries_text(writer, "Tags", "Tag", &self.tags)?;
+        write_ranks_text(writer, "Chatty sites", &self.chatty_sites)?;
+        write_ranks_text(writer, "Chatty tags", &self.chatty_tags)
+    }
+}
+
+/// Compare the `(questions, words)` counts of two sets of entries.
+fn diff_counts(
+    old: &HashMap<String, (usize, usize)>,
+    new: &HashMap<String, (usize, usize)>,
+) -> EntriesDiff {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut entries_diff = EntriesDiff::default();
+
+    for name in names {
+        match (old.get(name), new.get(name)) {
+            (Some(_), None) => entries_diff.removed.push(name.clone()),
+            (None, Some(_)) => entries_diff.added.push(name.clone()),
+            (Some(&old_counts), Some(&new_counts)) if old_counts != new_counts => {
+                let (old_questions, old_words) = old_counts;
+                let (new_questions, new_words) = new_counts;
+                let old_ratio = ratio(old_questions, old_words);
+                let new_ratio = ratio(new_questions, new_words);
+                entries_diff.changed.push(CountsChange {
+                    name: name.clone(),
+                    old_questions,
+                    new_questions,
+                    questions_delta: new_questions as i64 - old_questions as i64,
+                    old_words,
+                    new_words,
+                    words_delta: new_words as i64 - old_words as i64,
+                    old_ratio,
+                    new_ratio,
+                    ratio_delta: new_ratio - old_ratio,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    entries_diff
+}
+
+/// Compare two rankings, keeping the entries whose rank changed.
+///
+/// The changes are sorted by new rank, with the entries that left the ranking last.
+///
+fn diff_ranks(old: &[String], new: &[String]) -> Vec<RankChange> {
+    let rank = |ranking: &[String], name: &String| {
+        ranking
+            .iter()
+            .position(|entry| entry == name)
+            .map(|position| position + 1)
+    };
+
+    let mut changes: Vec<RankChange> = new
+        .iter()
+        .chain(old.iter().filter(|name| !new.contains(name)))
+        .map(|name| RankChange {
+            name: name.clone(),
+            old_rank: rank(old, name),
+            new_rank: rank(new, name),
+        })
+        .filter(|change| change.old_rank != change.new_rank)
+        .collect();
+
+    changes.sort_by_key(|change| (change.new_rank.is_none(), change.new_rank, change.old_rank));
+    changes
+}
+
+/// Write the added, removed and changed entries of a diff.
+fn write_entries_text<W: Write>(
+    writer: &mut W,
+    title: &str,
+    header: &str,
+    entries_diff: &EntriesDiff,
+) -> io::Result<()> {
+    writeln!(writer, "{}", title)?;
+    writeln!(writer, "  Added: {}", names_text(&entries_diff.added))?;
+    writeln!(writer, "  Removed: {}", names_text(&entries_diff.removed))?;
+    if !entries_diff.changed.is_empty() {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "  {:<40} {:>12} {:>10} {:>14} {:>10} {:>10} {:>10}",
+            header, "Questions", "Delta", "Words", "Delta", "Ratio", "Delta"
+        )?;
+        for change in &entries_diff.changed {
+            writeln!(
+                writer,
+                "  {:<40} {:>12} {:>+10} {:>14} {:>+10} {:>10.2} {:>+10.2}",
+                change.name,
+                change.new_questions,
+                change.questions_delta,
+                change.new_words,
+                change.words_delta,
+                change.new_ratio,
+                change.ratio_delta
+            )?;
+        }
+    }
+    writeln!(writer)
+}
+
+/// Write the rank changes of a ranking.
+fn write_ranks_text<W: Write>(
+    writer: &mut W,
+    title: &str,
+    changes: &[RankChange],
+) -> io::Result<()> {
+    writeln!(writer, "{}", title)?;
+    if changes.is_empty() {
+        writeln!(writer, "  No changes.")?;
+    }
+    for change in changes {
+        writeln!(
+            writer,
+            "  {}: {} -> {}",
+            change.name,
+            rank_text(change.old_rank),
+            rank_text(change.new_rank)
+        )?;
+    }
+    writeln!(writer)
+}
+
+/// Join names with commas, or return "none" if there are none.
+fn names_text(names: &[String]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Format a rank, using "-" for entries outside the ranking.
+fn rank_text(rank: Option<usize>) -> String {
+    rank.map_or_else(|| "-".to_string(), |rank| rank.to_string())
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors that can happen while processing the input files.
+///
+/// Line numbers start at 1 and refer to the decompressed contents of the file.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be opened or read.
+    Io { path: PathBuf, source: io::Error },
+    /// A line of a file is not valid JSON.
+    Json {
+        file: String,
+        line: usize,
+        source: serde_json::Error,
+    },
+    /// A line of a file is valid JSON but does not describe a question.
+    Schema {
+        file: String,
+        line: usize,
+        problem: SchemaProblem,
+    },
+    /// Two input files give sites with the same name, such as files with the same name in
+    /// two directories or a file next to its compressed copy.
+    DuplicateSite {
+        name: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// A previously produced result could not be read.
+    Result(serde_json::Error),
+}
+
+/// Problems that make a JSON line not describe a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaProblem {
+    /// The line is not a JSON object.
+    NotAnObject,
+    /// The object does not have the given field.
+    MissingField(String),
+    /// The given field does not have the expected type.
+    InvalidField(String),
+}
+
+/// Result type returned by the fallible functions of the library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            Error::Json { file, line, source } => {
+                write!(f, "{}:{}: invalid JSON: {}", file, line, source)
+            }
+            Error::Schema {
+                file,
+                line,
+                problem,
+            } => write!(f, "{}:{}: {}", file, line, problem),
+            Error::DuplicateSite {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} and {} are both the site {}",
+                first.display(),
+                second.display(),
+                name
+            ),
+            Error::Result(source) => write!(f, "invalid result: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Schema { .. } => None,
+            Error::DuplicateSite { .. } => None,
+            Error::Result(source) => Some(source),
+        }
+    }
+}
+
+impl fmt::Display for SchemaProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaProblem::NotAnObject => write!(f, "line is not a JSON object"),
+            SchemaProblem::MissingField(field) => write!(f, "missing field \"{}\"", field),
+            SchemaProblem::InvalidField(field) => {
+                write!(f, "field \"{}\" does not have the expected type", field)
+            }
+        }
+    }
+}
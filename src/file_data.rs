@@ -1,124 +1,1040 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use crate::utils::count_words;
+use crate::cooccurrence::{TagPairs, TopTagPairs};
+use crate::dedup::{duplicate_lines, find_duplicates, Duplicate, QuestionSignature};
+use crate::error::{Error, Result, SchemaProblem};
+use crate::heavy_hitters::{HeavyHitters, TopWords};
+use crate::input::{input_name, open_input, Compression};
+use crate::normalize::{normalize_text, NormalizedText};
+use crate::options::{ErrorPolicy, ProcessOptions, Schema, TagFormat};
+use crate::sketch::{DistinctSketch, LengthSketch};
+use crate::terms::{is_term, TermCounts, TermScore};
+use crate::utils::count_words_by_role;
+use memmap2::Mmap;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::path::Path;
+
+/// Approximate number of bytes read from a file before its lines are parsed.
+///
+/// Each file being processed holds at most two batches in memory: the one being
+/// parsed and the one being read, so the memory used by the input does not depend
+/// on the size of the files.
+///
+pub const BATCH_BYTES: usize = 8 * 1024 * 1024;
+
+/// Approximate size of the byte ranges in which uncompressed files are split.
+///
+/// Each range ends at a line break, so it holds only whole lines and can be
+/// parsed by a separate task.
+///
+pub const CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// Maximum number of line numbers kept in `SkippedLines::sample_lines`.
+pub const SKIPPED_SAMPLE_SIZE: usize = 10;
 
 /// Struct representing data extracted from a file.
 ///
 /// This struct contains information about a file, including its filename,
-/// total word count, total line count, tag counts, and top tags.
+/// total word count, total line count, tag counts, top tags, the lines
+/// that were skipped because they were not valid questions and, when requested,
+/// the distribution of the question lengths of the file and of each tag and their
+/// words broken down by the role of each text, the words of their code blocks and the
+/// pairs of tags that appear together, with their top pairs, and the terms of the file
+/// and of each tag, with the distinctive terms of the file, and the distinct words of the
+/// file and of each tag, its most frequent words and its near-duplicate questions.
+///
+/// It serializes to the JSON object that describes a site in the result, so the
+/// filename, which is the key of that object, is not part of it.
 ///
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct FileData {
+    #[serde(skip)]
     pub filename: String,
-    pub total_word_count: usize,
+    #[serde(rename = "questions")]
     pub total_line_count: usize,
+    #[serde(rename = "words")]
+    pub total_word_count: usize,
+    #[serde(rename = "tags", with = "tag_counts_serde")]
     pub tag_counts: HashMap<String, (usize, usize)>,
+    #[serde(rename = "chatty_tags")]
     pub top_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "SkippedLines::is_empty")]
+    pub skipped: SkippedLines,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lengths: Option<LengthSketch>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_lengths: HashMap<String, LengthSketch>,
+    #[serde(default, skip_serializing_if = "TextCounts::is_empty")]
+    pub texts: TextCounts,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_texts: HashMap<String, TextCounts>,
+    #[serde(rename = "code_words", default, skip_serializing_if = "is_zero")]
+    pub code_word_count: usize,
+    #[serde(default, skip_serializing_if = "TagPairs::is_empty")]
+    pub tag_pairs: TagPairs,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_tag_pairs: Option<TopTagPairs>,
+    #[serde(default, skip_serializing_if = "TermCounts::is_empty")]
+    pub terms: TermCounts,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_terms: HashMap<String, TermCounts>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_terms: Vec<TermScore>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distinct_words: Option<DistinctSketch>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_distinct_words: HashMap<String, DistinctSketch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heavy_hitters: Option<HeavyHitters>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_words: Option<TopWords>,
+    /// MinHash signatures of the questions, kept only until their duplicates are found.
+    #[serde(skip)]
+    pub signatures: Vec<QuestionSignature>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<Duplicate>,
+    #[serde(
+        rename = "excluded_duplicates",
+        default,
+        skip_serializing_if = "is_zero"
+    )]
+    pub excluded_duplicate_count: usize,
+}
+
+/// Whether a count is zero, so it is left out of the JSON.
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// Struct representing the words of a set of questions broken down by text role.
+///
+/// The role of each entry of the `texts` array is given by `ProcessOptions::text_roles`.
+/// The sum of the title, body and other words is the total word count.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextCounts {
+    /// Words of the titles.
+    pub title_words: usize,
+    /// Words of the bodies.
+    pub body_words: usize,
+    /// Words of the texts that are neither a title nor a body.
+    pub other_words: usize,
+    /// Number of entries of the `texts` arrays.
+    pub entries: usize,
+}
+
+impl TextCounts {
+    /// Total number of words.
+    pub fn words(&self) -> usize {
+        self.title_words + self.body_words + self.other_words
+    }
+
+    /// Whether no text was counted.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Add the counts of another `TextCounts` to this one.
+    pub fn merge(&mut self, other: &TextCounts) {
+        self.title_words += other.title_words;
+        self.body_words += other.body_words;
+        self.other_words += other.other_words;
+        self.entries += other.entries;
+    }
+}
+
+/// Struct representing the lines skipped while processing a file.
+///
+/// Lines are only skipped when processing with `ErrorPolicy::Skip`. Besides the
+/// number of lines skipped for each reason, it keeps the numbers of the first
+/// `SKIPPED_SAMPLE_SIZE` skipped lines.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedLines {
+    /// Lines that are not valid JSON.
+    pub malformed: usize,
+    /// Lines that are valid JSON but not an object.
+    pub non_object: usize,
+    /// Objects with a missing field, or a field of the wrong type.
+    pub missing_field: usize,
+    /// Numbers of the first skipped lines, in increasing order.
+    pub sample_lines: Vec<usize>,
+}
+
+impl SkippedLines {
+    /// Total number of skipped lines.
+    pub fn total(&self) -> usize {
+        self.malformed + self.non_object + self.missing_field
+    }
+
+    /// Whether no line was skipped.
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Add the skipped lines of another `SkippedLines` to this one.
+    pub fn merge(&mut self, other: SkippedLines) {
+        self.malformed += other.malformed;
+        self.non_object += other.non_object;
+        self.missing_field += other.missing_field;
+        self.sample_lines.extend(other.sample_lines);
+        self.sample_lines.sort_unstable();
+        self.sample_lines.truncate(SKIPPED_SAMPLE_SIZE);
+    }
+
+    /// Count a skipped line, identified by its position in the batch.
+    fn record(&mut self, err: &LineError, position: usize) {
+        match err {
+            LineError::Json(_) => self.malformed += 1,
+            LineError::Schema(SchemaProblem::NotAnObject) => self.non_object += 1,
+            LineError::Schema(_) => self.missing_field += 1,
+        }
+        self.merge(SkippedLines {
+            sample_lines: vec![position],
+            ..Default::default()
+        });
+    }
+}
+
+impl FileData {
+    /// Create an empty `FileData` for the given file name.
+    pub fn new(filename: String) -> Self {
+        FileData {
+            filename,
+            ..Default::default()
+        }
+    }
+
+    /// Add the counts of another `FileData` to this one.
+    ///
+    /// This function sums the word and line counts, the per-tag counts, the skipped lines
+    /// and the length distributions of `other` into `self`. It is used to combine the partial
+    /// results of the fork-join reduce. The file name and the top tags of `self` are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `other`: The `FileData` whose counts are added.
+    ///
+    pub fn merge(&mut self, other: FileData) {
+        self.total_word_count += other.total_word_count;
+        self.total_line_count += other.total_line_count;
+        for (tag, (question_count, word_count)) in other.tag_counts {
+            let tag_entry = self.tag_counts.entry(tag).or_insert((0, 0));
+            tag_entry.0 += question_count;
+            tag_entry.1 += word_count;
+        }
+        self.skipped.merge(other.skipped);
+        if let Some(lengths) = other.lengths {
+            self.lengths
+                .get_or_insert_with(LengthSketch::default)
+                .merge(&lengths);
+        }
+        for (tag, lengths) in other.tag_lengths {
+            self.tag_lengths.entry(tag).or_default().merge(&lengths);
+        }
+        self.texts.merge(&other.texts);
+        for (tag, texts) in other.tag_texts {
+            self.tag_texts.entry(tag).or_default().merge(&texts);
+        }
+        self.code_word_count += other.code_word_count;
+        self.tag_pairs.merge(&other.tag_pairs);
+        self.terms.merge(&other.terms);
+        for (tag, terms) in other.tag_terms {
+            self.tag_terms.entry(tag).or_default().merge(&terms);
+        }
+        if let Some(distinct_words) = other.distinct_words {
+            self.distinct_words
+                .get_or_insert_with(DistinctSketch::default)
+                .merge(&distinct_words);
+        }
+        if let Some(heavy_hitters) = other.heavy_hitters {
+            self.heavy_hitters
+                .get_or_insert_with(HeavyHitters::default)
+                .merge(&heavy_hitters);
+        }
+        for (tag, distinct_words) in other.tag_distinct_words {
+            self.tag_distinct_words
+                .entry(tag)
+                .or_default()
+                .merge(&distinct_words);
+        }
+        self.signatures.extend(other.signatures);
+        self.duplicates.extend(other.duplicates);
+        self.excluded_duplicate_count += other.excluded_duplicate_count;
+    }
+
+    /// Add the counts of the lines that follow the ones already counted.
+    ///
+    /// Unlike `merge`, the heavy hitters are pruned afterwards, so appending the batches
+    /// of a file in order gives the same summary no matter how the lines of every batch
+    /// were split between tasks.
+    ///
+    pub(crate) fn append(&mut self, other: FileData) {
+        self.merge(other);
+        if let Some(heavy_hitters) = &mut self.heavy_hitters {
+            heavy_hitters.prune();
+        }
+    }
+
+    /// Drop the state needed to merge the file with others, keeping only its reports.
+    pub(crate) fn clear_state(&mut self) {
+        self.tag_pairs = TagPairs::default();
+        self.terms = TermCounts::default();
+        self.tag_terms = HashMap::new();
+        self.heavy_hitters = None;
+        if let Some(distinct_words) = &mut self.distinct_words {
+            distinct_words.drop_registers();
+        }
+        for distinct_words in self.tag_distinct_words.values_mut() {
+            distinct_words.drop_registers();
+        }
+    }
+
+    /// Prune the terms of the file and of every tag, which is only done between merges
+    /// done in a fixed order so it does not depend on how the lines were split.
+    fn prune_terms(&mut self, max_terms: usize) {
+        self.terms.prune(max_terms);
+        for terms in self.tag_terms.values_mut() {
+            terms.prune(max_terms);
+        }
+    }
+
+    /// Cap the terms, which are pruned to at most twice `options.max_terms` while the
+    /// lines of the file are counted, once every line is.
+    fn finish(&mut self, options: &ProcessOptions) {
+        self.terms.truncate(options.max_terms);
+        for terms in self.tag_terms.values_mut() {
+            terms.truncate(options.max_terms);
+        }
+    }
+
+    /// Parse a JSONL line and add its question to the counts.
+    ///
+    /// The signature of the question, if requested, takes `offset` as its line until the
+    /// offsets of the batch are translated to line numbers.
+    ///
+    fn record_line(
+        &mut self,
+        line: &[u8],
+        offset: usize,
+        options: &ProcessOptions,
+    ) -> std::result::Result<(), LineError> {
+        let v: Value = serde_json::from_slice(line).map_err(LineError::Json)?;
+        if !v.is_object() {
+            return Err(LineError::Schema(SchemaProblem::NotAnObject));
+        }
+
+        let texts = question_texts(&v, &options.schema, options.require_fields)?;
+        let tags = question_tags(&v, &options.schema, options.require_fields)?;
+
+        let normalized: Vec<NormalizedText> = texts
+            .iter()
+            .map(|text| normalize_text(text, &options.normalization))
+            .collect();
+        let texts: Vec<&str> = normalized.iter().map(|text| text.prose.as_ref()).collect();
+        let code_word_count: usize = normalized
+            .iter()
+            .map(|text| options.tokenizer.count_words(&text.code))
+            .sum();
+
+        let text_counts = options
+            .collect_texts
+            .then(|| count_words_by_role(&texts, &options.text_roles, &*options.tokenizer));
+        let word_count = match &text_counts {
+            Some(text_counts) => text_counts.words(),
+            None => texts
+                .iter()
+                .map(|text| options.tokenizer.count_words(text))
+                .sum(),
+        };
+        if options.collect_tag_pairs {
+            self.tag_pairs.record(&tags, options.max_pair_tags);
+        }
+        if options.collect_terms {
+            let terms: Vec<String> = texts
+                .iter()
+                .flat_map(|text| options.tokenizer.tokens(text))
+                .map(str::to_lowercase)
+                .filter(|word| is_term(word, &options.stopwords))
+                .collect();
+            for tag in &tags {
+                let tag_terms = self.tag_terms.entry(tag.to_string()).or_default();
+                for term in &terms {
+                    tag_terms.insert(term);
+                }
+            }
+            for term in &terms {
+                self.terms.insert(term);
+            }
+        }
+        if options.collect_distinct_words {
+            let hashes: Vec<u64> = texts
+                .iter()
+                .flat_map(|text| options.tokenizer.tokens(text))
+                .map(|word| DistinctSketch::hash_word(&word.to_lowercase()))
+                .collect();
+            let new_sketch = || DistinctSketch::new(options.distinct_precision);
+            let site_words = self.distinct_words.get_or_insert_with(new_sketch);
+            for &hash in &hashes {
+                site_words.insert_hash(hash);
+            }
+            for tag in &tags {
+                let tag_words = self
+                    .tag_distinct_words
+                    .entry(tag.to_string())
+                    .or_insert_with(new_sketch);
+                for &hash in &hashes {
+                    tag_words.insert_hash(hash);
+                }
+            }
+        }
+        if options.collect_heavy_hitters {
+            let heavy_hitters = self
+                .heavy_hitters
+                .get_or_insert_with(|| HeavyHitters::new(options.heavy_hitter_capacity));
+            for word in texts.iter().flat_map(|text| options.tokenizer.tokens(text)) {
+                heavy_hitters.insert(&word.to_lowercase());
+            }
+        }
+        if options.detect_duplicates {
+            let words: Vec<String> = texts
+                .iter()
+                .flat_map(|text| options.tokenizer.tokens(text))
+                .map(str::to_lowercase)
+                .collect();
+            self.signatures
+                .extend(QuestionSignature::new(offset, &words, &options.minhash));
+        }
+        for tag in tags {
+            let tag_entry = self.tag_counts.entry(tag.to_string()).or_insert((0, 0));
+            tag_entry.0 += 1;
+            tag_entry.1 += word_count;
+            if options.collect_lengths {
+                self.tag_lengths
+                    .entry(tag.to_string())
+                    .or_default()
+                    .insert(word_count);
+            }
+            if let Some(text_counts) = &text_counts {
+                self.tag_texts
+                    .entry(tag.to_string())
+                    .or_default()
+                    .merge(text_counts);
+            }
+        }
+
+        if let Some(text_counts) = &text_counts {
+            self.texts.merge(text_counts);
+        }
+
+        if options.collect_lengths {
+            self.lengths
+                .get_or_insert_with(LengthSketch::default)
+                .insert(word_count);
+        }
+        self.total_word_count += word_count;
+        self.code_word_count += code_word_count;
+        self.total_line_count += 1;
+        Ok(())
+    }
+}
+
+/// Reason why a line could not be recorded, before its location is known.
+enum LineError {
+    Json(serde_json::Error),
+    Schema(SchemaProblem),
+}
+
+impl LineError {
+    /// Attach the location of the line to the error.
+    fn at(self, file: &str, line: usize) -> Error {
+        let file = file.to_string();
+        match self {
+            LineError::Json(source) => Error::Json { file, line, source },
+            LineError::Schema(problem) => Error::Schema {
+                file,
+                line,
+                problem,
+            },
+        }
+    }
+}
+
+/// Collect the texts of a question, in the order of `schema.text_pointers`.
+///
+/// Every text field holds either a string or an array, whose strings are taken in order.
+/// Fields that are missing or hold anything else add no texts, unless `required`.
+///
+fn question_texts<'a>(
+    question: &'a Value,
+    schema: &Schema,
+    required: bool,
+) -> std::result::Result<Vec<&'a str>, LineError> {
+    let mut texts = Vec::new();
+    for pointer in &schema.text_pointers {
+        match schema_field(question, pointer, required)? {
+            Some(Value::String(text)) => texts.push(text.as_str()),
+            Some(Value::Array(values)) => texts.extend(values.iter().filter_map(Value::as_str)),
+            Some(_) if required => return Err(invalid_field(pointer)),
+            _ => {}
+        }
+    }
+    Ok(texts)
+}
+
+/// Collect the tags of a question from `schema.tag_pointer`.
+///
+/// The tag field holds either an array, whose strings are the tags, or a string that
+/// is read according to `schema.tag_format`. A field that is missing or holds anything
+/// else means no tags, unless `required`.
+///
+fn question_tags<'a>(
+    question: &'a Value,
+    schema: &Schema,
+    required: bool,
+) -> std::result::Result<Vec<&'a str>, LineError> {
+    let tags = match schema_field(question, &schema.tag_pointer, required)? {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        Some(Value::String(tags)) => match schema.tag_format {
+            TagFormat::Single => vec![tags.as_str()],
+            TagFormat::Enclosed { open, close } if open == close => tags
+                .split(open)
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            TagFormat::Enclosed { open, close } => tags
+                .split(close)
+                .filter_map(|tag| tag.trim().strip_prefix(open))
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            TagFormat::Separated(separator) => tags
+                .split(separator)
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        },
+        Some(_) if required => return Err(invalid_field(&schema.tag_pointer)),
+        _ => Vec::new(),
+    };
+    Ok(tags)
+}
+
+/// Get the field of a question at a JSON pointer, which is an error if it is missing and
+/// `required`.
+fn schema_field<'a>(
+    question: &'a Value,
+    pointer: &str,
+    required: bool,
+) -> std::result::Result<Option<&'a Value>, LineError> {
+    match question.pointer(pointer) {
+        None if required => Err(LineError::Schema(SchemaProblem::MissingField(
+            pointer.to_string(),
+        ))),
+        field => Ok(field),
+    }
+}
+
+/// Error of a field that does not have the expected type.
+fn invalid_field(pointer: &str) -> LineError {
+    LineError::Schema(SchemaProblem::InvalidField(pointer.to_string()))
 }
 
 /// Process files to extract data using the fork-join model.
 ///
 /// This function processes a collection of file paths in parallel using the fork-join model,
-/// filtering out files with the ".jsonl" extension, reading their contents, and extracting
-/// information such as total word count, total line count, and tag counts from the JSONL format.
+/// keeping only files with the ".jsonl" extension, optionally followed by a ".gz", ".zst" or
+/// ".bz2" compression suffix, and extracting information such as total word count, total line
+/// count, and tag counts from the JSONL format. Uncompressed files are memory-mapped and split
+/// into byte ranges parsed by separate tasks, while compressed files are streamed.
+///
+/// # Arguments
+///
+/// * `files`: A vector of `PathBuf` representing the paths to the files to be processed.
+///
+/// # Returns
+///
+/// A vector of `FileData` containing the extracted data from each processed file, or the
+/// error that prevented one of the files from being processed.
+///
+pub fn process_files(files: Vec<std::path::PathBuf>) -> Result<Vec<FileData>> {
+    process_files_with(files, &ProcessOptions::default())
+}
+
+/// Process files to extract data using the given options.
+///
+/// This function works like `process_files`, but lets the caller decide, among other
+/// things, whether invalid lines abort the processing or are skipped.
+/// With `options.detect_duplicates`, the near-duplicate questions of all the files are
+/// found once every file is processed, and with `options.exclude_duplicates` the files
+/// are processed again leaving them out of the counts.
+///
+/// # Arguments
+///
+/// * `files`: A vector of `PathBuf` representing the paths to the files to be processed.
+/// * `options`: The options that control the processing.
+///
+/// # Returns
+///
+/// A vector of `FileData` containing the extracted data from each processed file, or the
+/// error that prevented one of the files from being processed.
+///
+pub fn process_files_with(
+    files: Vec<std::path::PathBuf>,
+    options: &ProcessOptions,
+) -> Result<Vec<FileData>> {
+    if !options.detect_duplicates {
+        return process_files_excluding(files, options, &[]);
+    }
+    if !options.exclude_duplicates {
+        let mut sites = process_files_excluding(files, options, &[])?;
+        let duplicates = find_duplicates(&sites, &options.minhash, options.duplicate_threshold);
+        for (site, duplicates) in sites.iter_mut().zip(duplicates) {
+            site.signatures = Vec::new();
+            site.duplicates = duplicates;
+        }
+        return Ok(sites);
+    }
+
+    // The first pass only computes the signatures, and the second one counts every
+    // question but the duplicates found with them. Both passes reject the same lines, so
+    // an invalid line is never signed and then excluded as a duplicate. Only the inputs that are processed
+    // are kept, so both passes give the sites of the same files in the same order, even
+    // when two of them share a file name.
+    let files: Vec<std::path::PathBuf> = files
+        .into_iter()
+        .filter(|file_path| input_name(file_path).is_some())
+        .collect();
+    let signature_options = ProcessOptions {
+        on_error: options.on_error,
+        schema: options.schema.clone(),
+        require_fields: options.require_fields,
+        detect_duplicates: true,
+        minhash: options.minhash,
+        normalization: options.normalization,
+        tokenizer: options.tokenizer.clone(),
+        chunk_bytes: options.chunk_bytes,
+        batch_bytes: options.batch_bytes,
+        ..ProcessOptions::default()
+    };
+    let signed = process_files_excluding(files.clone(), &signature_options, &[])?;
+    let duplicates = find_duplicates(&signed, &options.minhash, options.duplicate_threshold);
+    let excluded = duplicate_lines(&duplicates);
+
+    let count_options = ProcessOptions {
+        detect_duplicates: false,
+        ..options.clone()
+    };
+    let mut sites = process_files_excluding(files, &count_options, &excluded)?;
+    for (site, duplicates) in sites.iter_mut().zip(duplicates) {
+        site.duplicates = duplicates;
+    }
+    Ok(sites)
+}
+
+/// Process files, leaving the given lines of every file out of the counts.
 ///
 /// # Arguments
 ///
 /// * `files`: A vector of `PathBuf` representing the paths to the files to be processed.
+/// * `options`: The options that control the processing.
+/// * `excluded`: The numbers of the lines left out of the counts, by position of the file
+///   in `files`. Files past its end have no lines left out.
 ///
 /// # Returns
 ///
-/// A vector of `FileData` containing the extracted data from each processed file.
+/// A vector of `FileData` containing the extracted data from each processed file, or the
+/// error that prevented one of the files from being processed.
 ///
-pub fn process_files(files: Vec<std::path::PathBuf>) -> Vec<FileData> {
+fn process_files_excluding(
+    files: Vec<std::path::PathBuf>,
+    options: &ProcessOptions,
+    excluded: &[BTreeSet<usize>],
+) -> Result<Vec<FileData>> {
+    let no_lines = BTreeSet::new();
     files
         .into_par_iter()
-        .filter_map(|file_path| {
-            if let Some(extension) = file_path.extension() {
-                if extension == "jsonl" {
-                    if let Some(file_name) = file_path.file_name() {
-                        if let Some(file_name) = file_name.to_str() {
-                            let file_content =
-                                std::fs::read_to_string(&file_path).unwrap_or_else(|err| {
-                                    eprintln!("Failed to read file: {}", err);
-                                    std::process::exit(1);
-                                });
-
-                            let (total_word_count, total_line_count, tag_counts) = file_content
-                                .lines()
-                                .collect::<Vec<_>>()
-                                .par_iter()
-                                .fold(
-                                    || (0, 0, HashMap::new()),
-                                    |(word_count, line_count, mut tag_counts), line| {
-                                        let v: Value = serde_json::from_str(line)
-    .unwrap_or_else(|err| {
-        eprintln!("Failed to parse JSON: {}", err);
-        std::process::exit(1);
-    });
-
-                                        let texts_vec = vec![];
-                                        let tags_vec = vec![];
-
-                                        let texts = v
-                                            .get("texts")
-                                            .and_then(|texts| texts.as_array())
-                                            .unwrap_or(&texts_vec);
-                                        let tags = v
-                                            .get("tags")
-                                            .and_then(|tags| tags.as_array())
-                                            .unwrap_or(&tags_vec);
-
-                                        for tag in tags {
-                                            if let Some(tag) = tag.as_str() {
-                                                let tag_entry =
-                                                    tag_counts
-                                                        .entry(tag.to_string())
-                                                        .or_insert((0, 0));
-                                                tag_entry.0 += 1;
-                                                tag_entry.1 += count_words(texts);
-                                            }
-                                        }
-
-                                        (
-                                            word_count + count_words(texts),
-                                            line_count + 1,
-                                            tag_counts,
-                                        )
-                                    },
-                                )
-                                .reduce(
-                                    || (0, 0, HashMap::new()),
-                                    |(word_count1, line_count1, mut tag_counts1),
-                                     (word_count2, line_count2, tag_counts2)| {
-                                        for (tag, (line_count, word_count)) in tag_counts2 {
-                                            let tag_entry =
-                                                tag_counts1.entry(tag).or_insert((0, 0));
-                                            tag_entry.0 += line_count;
-                                            tag_entry.1 += word_count;
-                                        }
-                                        (
-                                            word_count1 + word_count2,
-                                            line_count1 + line_count2,
-                                            tag_counts1,
-                                        )
-                                    },
-                                );
-
-                            return Some(FileData {
-                                filename: file_name.to_string(),
-                                total_word_count,
-                                total_line_count,
-                                tag_counts,
-                                top_tags: Vec::new(),
-                            });
-                        }
+        .enumerate()
+        .filter_map(|(index, file_path)| {
+            let (file_name, compression) = input_name(&file_path)?;
+            let excluded = excluded.get(index).unwrap_or(&no_lines);
+
+            if compression == Compression::None {
+                return Some(process_mapped(&file_path, file_name, options, excluded));
+            }
+
+            Some(
+                open_input(&file_path, compression)
+                    .map_err(|source| io_error(&file_path, source))
+                    .and_then(|reader| {
+                        process_reader(reader, &file_path, file_name, options, excluded)
+                    }),
+            )
+        })
+        .map(|file_data| {
+            file_data.map(|mut file_data| {
+                file_data.finish(options);
+                file_data
+            })
+        })
+        .collect()
+}
+
+/// Process an uncompressed file split into byte ranges.
+///
+/// This function memory-maps the file, splits it into ranges of about `options.chunk_bytes`
+/// bytes that end at a line break, and parses as many ranges in parallel as there are
+/// threads, appending the partial `FileData` of each range in order.
+///
+/// # Arguments
+///
+/// * `file_path`: The path of the file.
+/// * `file_name`: The name given to the resulting `FileData`.
+/// * `options`: The options that control the processing.
+/// * `excluded`: The numbers of the lines left out of the counts.
+///
+/// # Returns
+///
+/// The `FileData` with the counts of every line of the file, or the error of one of its lines.
+///
+fn process_mapped(
+    file_path: &Path,
+    file_name: String,
+    options: &ProcessOptions,
+    excluded: &BTreeSet<usize>,
+) -> Result<FileData> {
+    let file = File::open(file_path).map_err(|source| io_error(file_path, source))?;
+
+    // SAFETY: the input files are only read while they are processed. Modifying
+    // them at the same time is not supported.
+    let mmap = unsafe { Mmap::map(&file) }.map_err(|source| io_error(file_path, source))?;
+
+    let ranges = chunk_ranges(&mmap, options.chunk_bytes);
+    let first_lines = first_line_numbers(&mmap, &ranges);
+
+    let tasks: Vec<(Range<usize>, usize)> = ranges.into_iter().zip(first_lines).collect();
+
+    let mut file_data = FileData::default();
+    for window in tasks.chunks(rayon::current_num_threads()) {
+        let partials = window
+            .par_iter()
+            .map(|(range, first_line)| {
+                parse_batch(
+                    &mmap[range.clone()],
+                    *first_line,
+                    &file_name,
+                    options,
+                    excluded,
+                )
+            })
+            .collect::<Vec<Result<FileData>>>();
+        // The ranges are checked in order, so the error reported is the one of the first
+        // invalid line of the file, no matter which task found it first.
+        for partial in partials {
+            file_data.append(partial?);
+            file_data.prune_terms(options.max_terms);
+        }
+    }
+
+    file_data.filename = file_name;
+    Ok(file_data)
+}
+
+/// Compute the number of the first line of every range, counting the lines in parallel.
+fn first_line_numbers(bytes: &[u8], ranges: &[Range<usize>]) -> Vec<usize> {
+    let line_counts: Vec<usize> = ranges
+        .par_iter()
+        .map(|range| count_lines(&bytes[range.clone()]))
+        .collect();
+
+    line_counts
+        .iter()
+        .scan(1, |first_line, line_count| {
+            let range_first_line = *first_line;
+            *first_line += line_count;
+            Some(range_first_line)
+        })
+        .collect()
+}
+
+/// Split a buffer into ranges of about `chunk_bytes` bytes that end at a line break.
+///
+/// Every range but the last one ends right after a `\n`, so no line is split
+/// between two ranges.
+///
+/// # Arguments
+///
+/// * `bytes`: The buffer to split.
+/// * `chunk_bytes`: The minimum size of every range but the last one.
+///
+/// # Returns
+///
+/// The ranges, in order, covering the whole buffer.
+///
+pub fn chunk_ranges(bytes: &[u8], chunk_bytes: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = (start + chunk_bytes.max(1)).min(bytes.len());
+        let end = bytes[end - 1..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(bytes.len(), |position| end + position);
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+/// Process the lines of a reader in bounded batches.
+///
+/// This function reads batches of about `options.batch_bytes` bytes of whole lines and
+/// parses the lines of each batch in parallel, folding them into partial `FileData`
+/// values that are reduced into a single one. The next batch is read while the current
+/// one is being parsed.
+///
+/// # Arguments
+///
+/// * `reader`: The reader over the contents of the file.
+/// * `file_path`: The path of the file, used to report read errors.
+/// * `file_name`: The name given to the resulting `FileData`.
+/// * `options`: The options that control the processing.
+/// * `excluded`: The numbers of the lines left out of the counts.
+///
+/// # Returns
+///
+/// The `FileData` with the counts of every line read, or the first error found.
+///
+fn process_reader<R: BufRead + Send>(
+    mut reader: R,
+    file_path: &Path,
+    file_name: String,
+    options: &ProcessOptions,
+    excluded: &BTreeSet<usize>,
+) -> Result<FileData> {
+    let batch_bytes = options.batch_bytes;
+    let mut file_data = FileData::new(file_name);
+    let mut first_line = 1;
+    let mut batch =
+        read_batch(&mut reader, batch_bytes).map_err(|source| io_error(file_path, source))?;
+
+    while !batch.is_empty() {
+        let (batch_data, next_batch) = rayon::join(
+            || parse_batch(&batch, first_line, &file_data.filename, options, excluded),
+            || read_batch(&mut reader, batch_bytes),
+        );
+        file_data.append(batch_data?);
+        file_data.prune_terms(options.max_terms);
+        first_line += count_lines(&batch);
+        batch = next_batch.map_err(|source| io_error(file_path, source))?;
+    }
+
+    Ok(file_data)
+}
+
+/// Read whole lines from a reader until the batch holds at least `batch_bytes` bytes.
+///
+/// Every line is read whole, with its line break, so no line is split between two
+/// batches. The last line of the reader is read even if it does not end in a line break.
+/// Lines are not decoded, so a line that is not valid UTF-8 only fails when it is parsed.
+///
+/// # Arguments
+///
+/// * `reader`: The reader over the lines.
+/// * `batch_bytes`: The minimum size of the batch, unless the reader ends before it.
+///
+/// # Returns
+///
+/// The lines read, empty once the reader is exhausted.
+///
+pub fn read_batch<R: BufRead>(reader: &mut R, batch_bytes: usize) -> io::Result<Vec<u8>> {
+    let mut batch = Vec::new();
+    while batch.len() < batch_bytes.max(1) {
+        if reader.read_until(b'\n', &mut batch)? == 0 {
+            break;
+        }
+    }
+    Ok(batch)
+}
+
+/// Parse the lines of a batch in parallel.
+///
+/// Invalid lines, including those that are not valid UTF-8, either abort the parsing or
+/// are recorded as skipped, depending on `options.on_error`. Excluded lines are only
+/// counted in `excluded_duplicate_count`.
+///
+/// # Arguments
+///
+/// * `batch`: The lines to parse.
+/// * `first_line`: The number of the first line of the batch within its file.
+/// * `file_name`: The name of the file, used to report errors.
+/// * `options`: The options that control the processing.
+/// * `excluded`: The numbers of the lines of the file left out of the counts.
+///
+/// # Returns
+///
+/// The `FileData` with the counts of the lines of the batch, or the error of one of them.
+///
+fn parse_batch(
+    batch: &[u8],
+    first_line: usize,
+    file_name: &str,
+    options: &ProcessOptions,
+    excluded: &BTreeSet<usize>,
+) -> Result<FileData> {
+    let excluded = line_offsets(batch, first_line, excluded);
+
+    // While the batch is parsed, the sampled skipped lines and the signatures hold
+    // byte offsets within the batch, which are translated to line numbers at the end.
+    let mut batch_data = batch
+        .par_split(|&byte| byte == b'\n')
+        .filter(|line| line.as_ptr() as usize - (batch.as_ptr() as usize) < batch.len())
+        .fold(
+            || Ok(FileData::default()),
+            |file_data: std::result::Result<FileData, (usize, LineError)>, line| {
+                // The lines of every task are in order, so those after an error are skipped.
+                let mut file_data = file_data?;
+                let offset = line.as_ptr() as usize - batch.as_ptr() as usize;
+                if excluded.contains(&offset) {
+                    file_data.excluded_duplicate_count += 1;
+                    return Ok(file_data);
+                }
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                if let Err(err) = file_data.record_line(line, offset, options) {
+                    match options.on_error {
+                        ErrorPolicy::Fail => return Err((offset, err)),
+                        ErrorPolicy::Skip => file_data.skipped.record(&err, offset),
                     }
                 }
+                Ok(file_data)
+            },
+        )
+        // An error does not stop the other tasks, so the first invalid line of the batch
+        // is the one reported, no matter how the lines were split between them.
+        .reduce(
+            || Ok(FileData::default()),
+            |file_data1, file_data2| match (file_data1, file_data2) {
+                (Ok(mut file_data1), Ok(file_data2)) => {
+                    file_data1.merge(file_data2);
+                    Ok(file_data1)
+                }
+                (Err(err1), Err(err2)) => Err(if err1.0 <= err2.0 { err1 } else { err2 }),
+                (Err(err), Ok(_)) | (Ok(_), Err(err)) => Err(err),
+            },
+        )
+        .map_err(|(offset, err)| err.at(file_name, first_line + count_lines(&batch[..offset])))?;
+
+    if let Some(heavy_hitters) = &mut batch_data.heavy_hitters {
+        heavy_hitters.prune();
+    }
+    batch_data.prune_terms(options.max_terms);
+    batch_data.skipped.sample_lines =
+        line_numbers(batch, first_line, &batch_data.skipped.sample_lines);
+    batch_data
+        .signatures
+        .sort_unstable_by_key(|signature| signature.line);
+    let offsets: Vec<usize> = batch_data
+        .signatures
+        .iter()
+        .map(|signature| signature.line)
+        .collect();
+    for (signature, line) in batch_data
+        .signatures
+        .iter_mut()
+        .zip(line_numbers(batch, first_line, &offsets))
+    {
+        signature.line = line;
+    }
+    Ok(batch_data)
+}
+
+/// Find the byte offsets within a batch of the lines of the file that it holds.
+fn line_offsets(bytes: &[u8], first_line: usize, lines: &BTreeSet<usize>) -> HashSet<usize> {
+    let mut offsets = HashSet::new();
+    let mut line = first_line;
+    let mut position = 0;
+    for &wanted in lines.range(first_line..) {
+        while line < wanted {
+            match bytes[position..].iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    position += end + 1;
+                    line += 1;
+                }
+                None => return offsets,
             }
-            None
+        }
+        if position >= bytes.len() {
+            break;
+        }
+        offsets.insert(position);
+    }
+    offsets
+}
+
+/// Translate increasing byte offsets within a buffer to line numbers.
+fn line_numbers(bytes: &[u8], first_line: usize, offsets: &[usize]) -> Vec<usize> {
+    let mut line = first_line;
+    let mut position = 0;
+    offsets
+        .iter()
+        .map(|&offset| {
+            line += count_lines(&bytes[position..offset]);
+            position = offset;
+            line
         })
         .collect()
 }
+
+/// Count the line breaks in a buffer.
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&byte| byte == b'\n').count()
+}
+
+/// Wrap an IO error with the path of the file it happened on.
+fn io_error(path: &Path, source: io::Error) -> Error {
+    Error::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Serialization of tag counts as `{"questions": .., "words": ..}` objects.
+///
+/// Tag counts are kept in memory as `(questions, words)` tuples; this module is
+/// used with `#[serde(with = "tag_counts_serde")]` to give them named fields.
+///
+pub(crate) mod tag_counts_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct TagCounts {
+        questions: usize,
+        words: usize,
+    }
+
+    pub fn serialize<S: Serializer>(
+        tag_counts: &HashMap<String, (usize, usize)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            tag_counts
+                .iter()
+                .map(|(tag, &(questions, words))| (tag, TagCounts { questions, words })),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, (usize, usize)>, D::Error> {
+        let tag_counts = HashMap::<String, TagCounts>::deserialize(deserializer)?;
+        Ok(tag_counts
+            .into_iter()
+            .map(|(tag, counts)| (tag, (counts.questions, counts.words)))
+            .collect())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::top_k::par_top_k;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Default number of counters of `HeavyHitters`.
+pub const DEFAULT_HEAVY_HITTER_CAPACITY: usize = 1_000;
+
+/// Struct representing the most frequent words of a set of questions.
+///
+/// This is a mergeable Misra-Gries summary, the counterpart of Space-Saving that
+/// underestimates counts instead of overestimating them. Words are counted exactly, and
+/// summaries are merged by adding their counters, until the summary is pruned: when there
+/// are more than twice `capacity` counters, the count of the `capacity + 1`-th most
+/// frequent word is subtracted from every counter, and the counters that reach zero are
+/// dropped. Which counters are dropped only depends on their counts, but pruning before
+/// or after a merge does not give the same summary, so summaries are only pruned between
+/// merges done in a fixed order, such as once per batch of lines and after merging the
+/// batches of a file in order, to give the same summary no matter how the lines were
+/// split between threads.
+///
+/// The count of every word is at most `error` below its true count, and `error` is at
+/// most `words / (capacity + 1)`, so every word more frequent than that is kept.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeavyHitters {
+    capacity: usize,
+    words: u64,
+    error: u64,
+    counters: HashMap<String, u64>,
+}
+
+impl HeavyHitters {
+    /// Create an empty summary with `capacity` counters.
+    pub fn new(capacity: usize) -> Self {
+        HeavyHitters {
+            capacity: capacity.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Count an occurrence of a word.
+    pub fn insert(&mut self, word: &str) {
+        self.words += 1;
+        match self.counters.get_mut(word) {
+            Some(count) => *count += 1,
+            None => {
+                self.counters.insert(word.to_string(), 1);
+            }
+        }
+    }
+
+    /// Add the words of another summary to this one, without pruning it.
+    ///
+    /// The merged summary keeps the larger of both capacities.
+    ///
+    pub fn merge(&mut self, other: &HeavyHitters) {
+        self.capacity = self.capacity.max(other.capacity);
+        self.words += other.words;
+        self.error += other.error;
+        for (word, &count) in &other.counters {
+            *self.counters.entry(word.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Number of words counted, counting repeated words every time.
+    pub fn words(&self) -> u64 {
+        self.words
+    }
+
+    /// Largest difference between the true count of a word and its count in the summary.
+    pub fn error(&self) -> u64 {
+        self.error
+    }
+
+    /// Count of a word in the summary, which is a lower bound of its true count.
+    pub fn count(&self, word: &str) -> u64 {
+        self.counters.get(word).copied().unwrap_or(0)
+    }
+
+    /// Find the `k` most frequent words of the summary.
+    ///
+    /// Words with the same count are ranked by name.
+    ///
+    /// # Returns
+    ///
+    /// The `TopWords` with the words, their count bounds and the error bound.
+    ///
+    pub fn top_k(&self, k: usize) -> TopWords {
+        let counts = self
+            .counters
+            .par_iter()
+            .map(|(word, &count)| (word, count as f64));
+        let words = par_top_k(counts, k)
+            .into_iter()
+            .map(|word| {
+                let count = self.count(word);
+                WordCount {
+                    word: word.clone(),
+                    count,
+                    max_count: count + self.error,
+                }
+            })
+            .collect();
+        TopWords {
+            words: self.words,
+            max_error: self.error,
+            top: words,
+        }
+    }
+
+    /// Subtract the count of the `capacity + 1`-th word from every counter once there are
+    /// more than twice `capacity` counters.
+    pub fn prune(&mut self) {
+        if self.capacity == 0 || self.counters.len() <= 2 * self.capacity {
+            return;
+        }
+        let mut counts: Vec<u64> = self.counters.values().copied().collect();
+        let (_, &mut threshold, _) =
+            counts.select_nth_unstable_by(self.capacity, |count1, count2| count2.cmp(count1));
+        self.counters.retain(|_, count| {
+            *count -= threshold.min(*count);
+            *count > 0
+        });
+        self.error += threshold;
+    }
+}
+
+/// Struct representing a frequent word and the bounds of its count.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordCount {
+    pub word: String,
+    /// Lower bound of the number of times the word appears.
+    pub count: u64,
+    /// Upper bound of the number of times the word appears.
+    pub max_count: u64,
+}
+
+/// Struct representing the most frequent words of a set of questions.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopWords {
+    /// Number of words counted.
+    pub words: u64,
+    /// Largest difference between the true count of a word and its `count`.
+    pub max_error: u64,
+    pub top: Vec<WordCount>,
+}
@@ -0,0 +1,120 @@
+use crate::error::{Error, Result};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Extension of the files that can be processed.
+const JSONL_EXTENSION: &str = ".jsonl";
+
+/// Compression applied to an input file.
+///
+/// The compression is detected from the suffix that follows the `.jsonl` extension
+/// in the file name.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Suffix that identifies the compression in a file name.
+    fn suffix(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+            Compression::Bzip2 => ".bz2",
+        }
+    }
+}
+
+/// Identify an input file from its name.
+///
+/// This function recognises `.jsonl` files as well as `.jsonl.gz`, `.jsonl.zst` and
+/// `.jsonl.bz2` files, which are decompressed while they are read.
+///
+/// # Arguments
+///
+/// * `path`: The path of the file.
+///
+/// # Returns
+///
+/// The name of the file without the compression suffix together with its compression,
+/// or `None` if the file is not a JSONL file.
+///
+pub fn input_name(path: &Path) -> Option<(String, Compression)> {
+    let file_name = path.file_name()?.to_str()?;
+    [
+        Compression::None,
+        Compression::Gzip,
+        Compression::Zstd,
+        Compression::Bzip2,
+    ]
+    .into_iter()
+    .find_map(|compression| {
+        let name = file_name.strip_suffix(compression.suffix())?;
+        if name.ends_with(JSONL_EXTENSION) {
+            Some((name.to_string(), compression))
+        } else {
+            None
+        }
+    })
+}
+
+/// Check that no two input files give sites with the same name.
+///
+/// Every site of a result is named after its file, without the compression suffix, so
+/// files with the same name in two directories, or a file next to its compressed copy,
+/// would be two sites that can't be told apart.
+///
+/// # Arguments
+///
+/// * `files`: The paths of the files to process.
+///
+/// # Returns
+///
+/// `Error::DuplicateSite` with the first two files that share a name, if any.
+///
+pub fn check_site_names(files: &[PathBuf]) -> Result<()> {
+    let mut paths: HashMap<String, &PathBuf> = HashMap::new();
+    for file_path in files {
+        let Some((file_name, _)) = input_name(file_path) else {
+            continue;
+        };
+        if let Some(first) = paths.insert(file_name.clone(), file_path) {
+            return Err(Error::DuplicateSite {
+                name: file_name,
+                first: first.clone(),
+                second: file_path.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Open an input file, decompressing it if needed.
+///
+/// # Arguments
+///
+/// * `path`: The path of the file.
+/// * `compression`: The compression of the file, as returned by `input_name`.
+///
+/// # Returns
+///
+/// A buffered reader over the decompressed contents of the file.
+///
+pub fn open_input(path: &Path, compression: Compression) -> io::Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path)?;
+    Ok(match compression {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(file))),
+    })
+}
@@ -1,9 +1,33 @@
 mod analysis;
+mod cooccurrence;
+mod dedup;
+mod diff;
+mod error;
 mod file_data;
+mod heavy_hitters;
+mod input;
+mod normalize;
+mod options;
 mod result;
+mod sketch;
+mod terms;
+mod tokenizer;
+mod top_k;
 mod utils;
 
 pub use analysis::*;
+pub use cooccurrence::*;
+pub use dedup::*;
+pub use diff::*;
+pub use error::*;
 pub use file_data::*;
+pub use heavy_hitters::*;
+pub use input::*;
+pub use normalize::*;
+pub use options::*;
 pub use result::*;
+pub use sketch::*;
+pub use terms::*;
+pub use tokenizer::*;
+pub use top_k::*;
 pub use utils::*;
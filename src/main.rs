@@ -1,41 +1,47 @@
-mod analysis;
-mod file_data;
-mod result;
-mod utils;
-
-use analysis::{
-    aggregate_tag_counts, generate_top_tags, top_10_filenames_highest_ratio,
-    top_10_tags_highest_ratio,
-};
-use file_data::process_files;
-use result::ResultJson;
-use std::env;
+mod cli;
+
+use clap::Parser;
+use cli::{Cli, Command, CommonArgs, DiffArgs, MergeArgs, OutputFormat, ProcessArgs};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
+use tp1::{check_site_names, process_files_with, ResultDiff, ResultJson};
 
-use crate::result::Totals;
+/// Padron identifier included in every result.
+const PADRON: &str = "102676";
 
 /// Entry point of the program.
 ///
-/// This function initializes the program, processes files in a directory,
-/// generates statistics, and prints the results.
-///
-/// # Arguments
-///
-/// * `args`: Command-line arguments passed to the program.
+/// This function parses the command-line arguments, processes the input files,
+/// merges previous results or compares two of them, and writes the outcome.
 ///
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let cli = Cli::parse();
 
-    let num_threads = if args.len() > 1 {
-        args[1].parse::<usize>().unwrap_or_else(|_| {
-            eprintln!("Invalid thread count");
-            std::process::exit(1);
-        })
-    } else {
-        1
-    };
+    let start_time = Instant::now();
+
+    match &cli.command {
+        Some(Command::Merge(args)) => {
+            set_num_threads(args.common.threads);
+            let result = merge(args);
+            write_result(result, &args.common);
+        }
+        Some(Command::Diff(args)) => diff(args),
+        None => {
+            set_num_threads(cli.process.common.threads);
+            let result = process(&cli.process);
+            write_result(result, &cli.process.common);
+        }
+    }
 
-    // Set the number of threads.
+    let end_time = Instant::now();
+    let elapsed_time = end_time - start_time;
+    eprintln!("Time taken: {:?}", elapsed_time);
+}
+
+/// Set the number of threads of the global Rayon thread pool.
+fn set_num_threads(num_threads: usize) {
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_threads)
         .build_global()
@@ -43,46 +49,151 @@ fn main() {
             eprintln!("Failed to set Rayon thread pool size: {}", err);
             std::process::exit(1);
         });
+}
 
-    let start_time = Instant::now();
+/// Process the input files and build their result.
+fn process(args: &ProcessArgs) -> ResultJson {
+    let files = collect_input_files(&args.inputs).unwrap_or_else(|err| {
+        eprintln!("Failed to collect file paths: {}", err);
+        std::process::exit(1);
+    });
+    check_site_names(&files).unwrap_or_else(|err| {
+        eprintln!("Failed to process files: {}", err);
+        std::process::exit(1);
+    });
 
-    let directory = "data";
+    let files_data = process_files_with(files, &args.process_options()).unwrap_or_else(|err| {
+        eprintln!("Failed to process files: {}", err);
+        std::process::exit(1);
+    });
 
-    let files = std::fs::read_dir(directory)
-        .unwrap_or_else(|err| {
-            eprintln!("Failed to open directory: {}", err);
-            std::process::exit(1);
-        })
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, std::io::Error>>()
-        .unwrap_or_else(|err| {
-            eprintln!("Failed to collect file paths: {}", err);
-            std::process::exit(1);
-        });
+    ResultJson::from_sites_with(
+        PADRON.to_string(),
+        files_data,
+        &args.common.ranking_options(),
+    )
+}
 
-    let mut files_data = process_files(files);
+/// Read the results of previous runs and merge them.
+fn merge(args: &MergeArgs) -> ResultJson {
+    let results: Vec<ResultJson> = args.results.iter().map(|path| read_result(path)).collect();
 
-    generate_top_tags(&mut files_data);
+    if let Some(path) = args
+        .results
+        .iter()
+        .zip(&results)
+        .find_map(|(path, result)| result.lacks_state().then_some(path))
+    {
+        eprintln!(
+            "Failed to merge results: {} was written without --state, which its tag pairs, \
+             terms, heavy hitters or distinct words need to be merged",
+            path.display()
+        );
+        std::process::exit(1);
+    }
 
-    let aggregated_tag_counts = aggregate_tag_counts(&files_data);
-    let top_10_tags: Vec<String> = top_10_tags_highest_ratio(&aggregated_tag_counts);
-    let top_10_filenames = top_10_filenames_highest_ratio(&files_data);
+    let ranking = args.common.ranking_options();
+    let missing_lengths = results
+        .iter()
+        .flat_map(|result| &result.sites)
+        .any(|site| site.total_line_count > 0 && site.lengths.is_none());
+    if ranking.metric.needs_lengths() && missing_lengths {
+        eprintln!(
+            "Failed to merge results: ranking by median or p90 needs the question lengths \
+             kept by runs ranked by one of those metrics"
+        );
+        std::process::exit(1);
+    }
 
-    // Create Result
-    let result = ResultJson {
-        padron: "102676".to_string(),
-        sites: files_data,
-        tags: aggregated_tag_counts,
-        totals: Totals {
-            chatty_sites: top_10_filenames,
-            chatty_tags: top_10_tags,
-        },
-    };
+    ResultJson::merge(results, &ranking)
+}
 
-    // Print Result
-    result.print();
+/// Compare two previous results and write their differences.
+fn diff(args: &DiffArgs) {
+    let result_diff = ResultDiff::between(&read_result(&args.old), &read_result(&args.new));
 
-    let end_time = Instant::now();
-    let elapsed_time = end_time - start_time;
-    eprintln!("Time taken: {:?}", elapsed_time);
+    write_output(args.output.as_ref(), |writer| match args.format {
+        OutputFormat::Json => result_diff.write_json(writer),
+        OutputFormat::Text => result_diff.write_text(writer),
+    })
+    .unwrap_or_else(|err| {
+        eprintln!("Failed to write diff: {}", err);
+        std::process::exit(1);
+    });
+}
+
+/// Read a result written by a previous run.
+fn read_result(path: &Path) -> ResultJson {
+    let file = File::open(path).unwrap_or_else(|err| {
+        eprintln!("Failed to open {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    ResultJson::from_reader(BufReader::new(file)).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", path.display(), err);
+        std::process::exit(1);
+    })
+}
+
+/// Write a result in the requested format, without its state unless requested.
+fn write_result(mut result: ResultJson, args: &CommonArgs) {
+    if !args.state {
+        result.clear_state();
+    }
+    let options = args.output_options();
+    write_output(args.output.as_ref(), |writer| match args.format {
+        OutputFormat::Json => result.write_json_with(writer, &options),
+        OutputFormat::Text => result.write_text_with(writer, &options),
+    })
+    .unwrap_or_else(|err| {
+        eprintln!("Failed to write result: {}", err);
+        std::process::exit(1);
+    });
+}
+
+/// Collect the files to process from the given inputs.
+///
+/// Directories are expanded to the entries they contain, sorted by path so the order of
+/// the sites, and which of two duplicate questions is kept, doesn't depend on the file
+/// system, while any other path is taken as a file to process.
+///
+/// # Arguments
+///
+/// * `inputs`: The files and directories given on the command line.
+///
+/// # Returns
+///
+/// The paths of the files to process, or the first error found while listing a directory.
+///
+fn collect_input_files(inputs: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries = std::fs::read_dir(input)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<PathBuf>>>()?;
+            entries.sort();
+            files.extend(entries);
+        } else if input.is_file() {
+            files.push(input.clone());
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", input.display()),
+            ));
+        }
+    }
+    Ok(files)
+}
+
+/// Write to the output file, or to the standard output when no file is given.
+fn write_output<F>(output: Option<&PathBuf>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut Box<dyn Write>) -> io::Result<()>,
+{
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    write(&mut writer)?;
+    writer.flush()
 }
@@ -0,0 +1,289 @@
+use crate::options::{CodeBlocks, Normalization};
+use std::borrow::Cow;
+
+/// Word that replaces every URL when `Normalization::collapse_urls` is set.
+pub const URL_TOKEN: &str = "URL";
+
+/// Struct representing a text after its normalization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedText<'a> {
+    /// Text whose words are counted.
+    pub prose: Cow<'a, str>,
+    /// Text of the code blocks, which is empty unless they are counted separately.
+    pub code: String,
+}
+
+/// Clean up a text before its words are counted.
+///
+/// Code blocks are taken out of the text first, so the HTML inside them is not mistaken
+/// for the markup of the text. Then the HTML is stripped and the URLs are collapsed, both
+/// in the text and in its code.
+///
+/// # Arguments
+///
+/// * `text`: The text to normalize.
+/// * `normalization`: The steps applied to the text.
+///
+/// # Returns
+///
+/// The `NormalizedText`, which borrows `text` when it is left as it is.
+///
+pub fn normalize_text<'a>(text: &'a str, normalization: &Normalization) -> NormalizedText<'a> {
+    if normalization.is_identity() {
+        return NormalizedText {
+            prose: Cow::Borrowed(text),
+            code: String::new(),
+        };
+    }
+
+    let (mut prose, mut code) = match normalization.code_blocks {
+        CodeBlocks::Keep => (text.to_string(), String::new()),
+        CodeBlocks::Exclude => (split_code_blocks(text).0, String::new()),
+        CodeBlocks::Separate => split_code_blocks(text),
+    };
+    if normalization.strip_html {
+        prose = strip_html(&prose);
+        code = strip_html(&code);
+    }
+    if normalization.collapse_urls {
+        prose = collapse_urls(&prose);
+        code = collapse_urls(&code);
+    }
+    NormalizedText {
+        prose: Cow::Owned(prose),
+        code,
+    }
+}
+
+/// Split a text into its prose and the contents of its HTML `pre` elements and its
+/// fenced and indented Markdown code blocks.
+fn split_code_blocks(text: &str) -> (String, String) {
+    let mut prose = String::new();
+    let mut code = String::new();
+    let mut rest = text;
+    while let Some(start) = find_pre(rest) {
+        split_markdown_code(&rest[..start], &mut prose, &mut code);
+        let contents = tag_end(rest.as_bytes(), start).unwrap_or(rest.len());
+        match find(&rest.as_bytes()[contents..], b"</pre>") {
+            Some(end) => {
+                code.push_str(&rest[contents..contents + end]);
+                rest = &rest[contents + end + "</pre>".len()..];
+            }
+            None => {
+                code.push_str(&rest[contents..]);
+                rest = "";
+            }
+        }
+        code.push('\n');
+        prose.push('\n');
+    }
+    split_markdown_code(rest, &mut prose, &mut code);
+    (prose, code)
+}
+
+/// Add the lines of a Markdown text to its prose or its code.
+///
+/// An indented line is code when it follows a blank line or another line of code, so a
+/// paragraph cannot be turned into code by indenting its following lines. A fence that
+/// is never closed runs until the end of the text.
+///
+fn split_markdown_code(text: &str, prose: &mut String, code: &mut String) {
+    let mut fence: Option<(u8, usize)> = None;
+    let mut previous_blank = true;
+    let mut indented_code = false;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if let Some((marker, length)) = fence {
+            if closes_fence(content, marker, length) {
+                fence = None;
+            } else {
+                code.push_str(content);
+                code.push('\n');
+            }
+            previous_blank = false;
+            continue;
+        }
+        if let Some(opening) = opening_fence(content) {
+            fence = Some(opening);
+            previous_blank = false;
+            indented_code = false;
+            continue;
+        }
+
+        let blank = content.trim().is_empty();
+        let indented = content.starts_with("    ") || content.starts_with('\t');
+        if !blank && indented && (previous_blank || indented_code) {
+            code.push_str(content);
+            code.push('\n');
+            indented_code = true;
+        } else {
+            prose.push_str(line);
+            if !blank {
+                indented_code = false;
+            }
+        }
+        previous_blank = blank;
+    }
+}
+
+/// Marker and length of the code fence opened by a line, if any.
+fn opening_fence(line: &str) -> Option<(u8, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let marker = *trimmed.as_bytes().first()?;
+    if marker != b'`' && marker != b'~' {
+        return None;
+    }
+    let length = trimmed.bytes().take_while(|&byte| byte == marker).count();
+    (length >= 3).then_some((marker, length))
+}
+
+/// Whether a line closes a code fence opened with `length` times `marker`.
+fn closes_fence(line: &str, marker: u8, length: usize) -> bool {
+    match opening_fence(line) {
+        Some((closing_marker, closing_length)) => {
+            let rest = line.trim_start_matches(' ')[closing_length..].trim();
+            closing_marker == marker && closing_length >= length && rest.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// Position of the first HTML `pre` element of a text, if any.
+fn find_pre(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut offset = 0;
+    while let Some(position) = find(&bytes[offset..], b"<pre") {
+        let start = offset + position;
+        match bytes.get(start + 4) {
+            Some(b'>') | Some(b' ') | Some(b'\t') | Some(b'\n') => return Some(start),
+            _ => offset = start + 4,
+        }
+    }
+    None
+}
+
+/// Remove the HTML tags and comments of a text and replace its character entities.
+///
+/// Tags are replaced by a space, so the words of adjacent elements are not joined.
+/// Unknown entities are replaced by a space too.
+///
+fn strip_html(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut stripped = String::with_capacity(text.len());
+    let mut position = 0;
+    while let Some(character) = text[position..].chars().next() {
+        match character {
+            '<' => {
+                if let Some(end) = tag_end(bytes, position) {
+                    stripped.push(' ');
+                    position = end;
+                    continue;
+                }
+            }
+            '&' => {
+                if let Some(end) = entity_end(bytes, position) {
+                    stripped.push(decode_entity(&text[position + 1..end - 1]).unwrap_or(' '));
+                    position = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        stripped.push(character);
+        position += character.len_utf8();
+    }
+    stripped
+}
+
+/// Character of an HTML entity, given its name without `&` and `;`.
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => None,
+    }
+}
+
+/// Replace every URL of a text by `URL_TOKEN`.
+///
+/// A URL starts with `http://`, `https://` or `www.` and runs until the next whitespace,
+/// leaving out any punctuation that closes it, as in `(see https://example.com).`.
+///
+fn collapse_urls(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for piece in text.split_inclusive(char::is_whitespace) {
+        let token = piece.trim_end_matches(char::is_whitespace);
+        match url_start(token) {
+            Some(start) => {
+                let url = &token[start..];
+                let closing =
+                    url.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}', '"', '\'']);
+                collapsed.push_str(&token[..start]);
+                collapsed.push_str(URL_TOKEN);
+                collapsed.push_str(&url[closing.len()..]);
+                collapsed.push_str(&piece[token.len()..]);
+            }
+            None => collapsed.push_str(piece),
+        }
+    }
+    collapsed
+}
+
+/// Position where the first URL of a word starts, if any.
+fn url_start(token: &str) -> Option<usize> {
+    token.char_indices().find_map(|(position, character)| {
+        let boundary = token[..position]
+            .chars()
+            .next_back()
+            .is_none_or(|previous| !previous.is_alphanumeric());
+        let url = ["http://", "https://", "www."]
+            .iter()
+            .any(|prefix| token[position..].starts_with(prefix));
+        (character.is_ascii_alphabetic() && boundary && url).then_some(position)
+    })
+}
+
+/// Position after the end of the HTML tag or comment that starts at `start`, if any.
+pub(crate) fn tag_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let next = *bytes.get(start + 1)?;
+    if !(next.is_ascii_alphabetic() || matches!(next, b'/' | b'!' | b'?')) {
+        return None;
+    }
+    if bytes[start..].starts_with(b"<!--") {
+        let end = find(&bytes[start + 4..], b"-->")?;
+        return Some(start + 4 + end + 3);
+    }
+    let end = bytes[start..].iter().position(|&byte| byte == b'>')?;
+    Some(start + end + 1)
+}
+
+/// Position after the end of the character entity that starts at `start`, if any.
+pub(crate) fn entity_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let name = bytes.get(start + 1..)?;
+    let length = name
+        .iter()
+        .take(32)
+        .position(|&byte| !(byte.is_ascii_alphanumeric() || byte == b'#'))?;
+    (length > 0 && name[length] == b';').then_some(start + 1 + length + 1)
+}
+
+/// Position of the first occurrence of `needle` in `haystack`, if any.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
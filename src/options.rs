@@ -0,0 +1,217 @@
+use crate::cooccurrence::DEFAULT_MAX_PAIR_TAGS;
+use crate::dedup::{MinHashOptions, DEFAULT_DUPLICATE_THRESHOLD};
+use crate::file_data::{BATCH_BYTES, CHUNK_BYTES};
+use crate::heavy_hitters::DEFAULT_HEAVY_HITTER_CAPACITY;
+use crate::sketch::DEFAULT_DISTINCT_PRECISION;
+use crate::terms::{stopword_set, DEFAULT_MAX_TERMS, ENGLISH_STOPWORDS, SPANISH_STOPWORDS};
+use crate::tokenizer::{Tokenizer, WhitespaceTokenizer};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// What to do with a line that does not describe a valid question.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop processing and return the error.
+    #[default]
+    Fail,
+    /// Leave the line out of the counts and record it in `FileData::skipped`.
+    Skip,
+}
+
+/// Role of a text of a question, given by its position among the texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextRole {
+    /// Title of the question.
+    Title,
+    /// Body of the question.
+    Body,
+    /// Any other text, counted only in `TextCounts::other_words`.
+    Other,
+}
+
+/// Roles of the texts in the StackExchange dumps: the title, then the body.
+pub const DEFAULT_TEXT_ROLES: [TextRole; 2] = [TextRole::Title, TextRole::Body];
+
+/// How a tag field that holds a single string is read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagFormat {
+    /// The string is a single tag.
+    Single,
+    /// Every tag is enclosed between two delimiters, as in `"<python><rust>"`. When both
+    /// delimiters are the same character, as in `"|python|rust|"`, the tags are the texts
+    /// between them.
+    Enclosed { open: char, close: char },
+    /// The tags are separated by a character, as in `"python,rust"`.
+    Separated(char),
+}
+
+/// Where the texts and tags of a question are in each JSON line.
+///
+/// Fields are addressed by JSON pointers, as in `/texts` or `/question/title`. The
+/// default schema reads the `texts` and `tags` arrays of the StackExchange dumps.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    /// Pointers to the text fields, each holding a string or an array of strings. Their
+    /// texts are taken in order, and their positions are the ones given roles by
+    /// `ProcessOptions::text_roles`.
+    pub text_pointers: Vec<String>,
+    /// Pointer to the tag field, holding an array of strings or a string.
+    pub tag_pointer: String,
+    /// How a tag field that holds a string is read. Arrays always hold one tag per string.
+    pub tag_format: TagFormat,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Schema {
+            text_pointers: vec!["/texts".to_string()],
+            tag_pointer: "/tags".to_string(),
+            tag_format: TagFormat::Single,
+        }
+    }
+}
+
+/// What to do with the code blocks of the texts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodeBlocks {
+    /// Count their words like any other word.
+    #[default]
+    Keep,
+    /// Leave their words out of the counts.
+    Exclude,
+    /// Count their words only in `FileData::code_word_count`.
+    Separate,
+}
+
+/// How the texts are cleaned up before their words are counted.
+///
+/// The default normalization leaves the texts as they are.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Remove HTML tags and comments, and replace character entities by their characters.
+    pub strip_html: bool,
+    /// What to do with fenced and indented Markdown code blocks and HTML `pre` elements.
+    pub code_blocks: CodeBlocks,
+    /// Replace every URL by the single word `URL`.
+    pub collapse_urls: bool,
+}
+
+impl Normalization {
+    /// Whether the texts are left as they are.
+    pub fn is_identity(&self) -> bool {
+        *self == Normalization::default()
+    }
+}
+
+/// Options that control how the input files are processed.
+///
+/// The default options reproduce the behaviour of `process_files`.
+///
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    /// Whether a line that is not a valid question, including one that is not valid
+    /// UTF-8, aborts the processing of its file or is skipped and counted in
+    /// `FileData::skipped`.
+    pub on_error: ErrorPolicy,
+    /// Where the texts and tags are in each line.
+    pub schema: Schema,
+    /// Treat a question whose text or tag fields are missing or have another type as
+    /// invalid, instead of counting it without those texts or tags.
+    pub require_fields: bool,
+    /// Keep the distribution of question lengths of every site and tag, in
+    /// `FileData::lengths` and `FileData::tag_lengths`.
+    pub collect_lengths: bool,
+    /// Break the words of every site and tag down by the role of their text, in
+    /// `FileData::texts` and `FileData::tag_texts`.
+    pub collect_texts: bool,
+    /// Role of each position of the texts of a question; positions past the end are
+    /// `TextRole::Other`.
+    pub text_roles: Vec<TextRole>,
+    /// Count the questions in which every pair of tags appears together, in
+    /// `FileData::tag_pairs`.
+    pub collect_tag_pairs: bool,
+    /// Maximum number of tags of a question whose pairs are counted.
+    pub max_pair_tags: usize,
+    /// Count the terms of every site and tag, in `FileData::terms` and
+    /// `FileData::tag_terms`, to find their distinctive terms.
+    pub collect_terms: bool,
+    /// Lowercase words that are not counted as terms.
+    pub stopwords: HashSet<String>,
+    /// Maximum number of distinct terms kept for each site and tag; zero keeps them all.
+    /// While a file is counted, at most twice as many are kept, which makes the counts
+    /// approximate once the least frequent are dropped.
+    pub max_terms: usize,
+    /// Estimate the distinct lowercase words of every site and tag, in
+    /// `FileData::distinct_words` and `FileData::tag_distinct_words`.
+    pub collect_distinct_words: bool,
+    /// Precision of the distinct word sketches, in `DISTINCT_PRECISION_RANGE`.
+    pub distinct_precision: u8,
+    /// Keep a summary of the most frequent lowercase words of every site, in
+    /// `FileData::heavy_hitters`.
+    pub collect_heavy_hitters: bool,
+    /// Number of counters of every heavy hitter summary.
+    pub heavy_hitter_capacity: usize,
+    /// Find the near-duplicate questions of all the sites, in `FileData::duplicates`.
+    pub detect_duplicates: bool,
+    /// How the MinHash signatures of the questions are computed and compared.
+    pub minhash: MinHashOptions,
+    /// Minimum estimated Jaccard similarity of the words of two near-duplicates.
+    pub duplicate_threshold: f64,
+    /// Leave the near-duplicates out of the counts, keeping the first question of every
+    /// cluster. The files are read twice: once to find the duplicates and once to count.
+    pub exclude_duplicates: bool,
+    /// How the texts are cleaned up before they are split into words.
+    pub normalization: Normalization,
+    /// How the texts are split into the words that are counted.
+    pub tokenizer: Arc<dyn Tokenizer>,
+    /// Approximate size of the byte ranges in which uncompressed files are split, as
+    /// `CHUNK_BYTES` by default.
+    pub chunk_bytes: usize,
+    /// Approximate size of the batches in which compressed files are read, as
+    /// `BATCH_BYTES` by default.
+    pub batch_bytes: usize,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions {
+            on_error: ErrorPolicy::default(),
+            schema: Schema::default(),
+            require_fields: false,
+            collect_lengths: false,
+            collect_texts: false,
+            text_roles: DEFAULT_TEXT_ROLES.to_vec(),
+            collect_tag_pairs: false,
+            max_pair_tags: DEFAULT_MAX_PAIR_TAGS,
+            collect_terms: false,
+            stopwords: stopword_set(&[ENGLISH_STOPWORDS, SPANISH_STOPWORDS]),
+            max_terms: DEFAULT_MAX_TERMS,
+            collect_distinct_words: false,
+            distinct_precision: DEFAULT_DISTINCT_PRECISION,
+            collect_heavy_hitters: false,
+            heavy_hitter_capacity: DEFAULT_HEAVY_HITTER_CAPACITY,
+            detect_duplicates: false,
+            minhash: MinHashOptions::default(),
+            duplicate_threshold: DEFAULT_DUPLICATE_THRESHOLD,
+            exclude_duplicates: false,
+            normalization: Normalization::default(),
+            tokenizer: Arc::new(WhitespaceTokenizer),
+            chunk_bytes: CHUNK_BYTES,
+            batch_bytes: BATCH_BYTES,
+        }
+    }
+}
+
+/// Options that control how a result is written.
+///
+/// The default options reproduce the output of `ResultJson::write_json` and
+/// `ResultJson::write_text`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Write the sites by filename and every object with its keys in alphabetical order,
+    /// so the same data always gives the same bytes.
+    pub sort_keys: bool,
+}
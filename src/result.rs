@@ -1,18 +1,61 @@
-use crate::file_data::FileData;
+use crate::analysis::{
+    aggregate_duplicate_clusters, aggregate_heavy_hitters, aggregate_tag_counts,
+    aggregate_tag_distinct_words, aggregate_tag_lengths, aggregate_tag_pairs, aggregate_tag_terms,
+    aggregate_tag_texts, generate_top_k_words, generate_top_n_tag_pairs, generate_top_n_tags_by,
+    generate_top_n_terms, top_n_filenames_by, top_n_tag_pairs, top_n_tag_terms, top_n_tags_by,
+    words_questions_ratio, RankingOptions,
+};
+use crate::cooccurrence::{TagPairStats, TagPairs, TopTagPairs};
+use crate::dedup::{DuplicateCluster, DUPLICATE_CLUSTER_SAMPLE_SIZE};
+use crate::error::{Error, Result};
+use crate::file_data::{tag_counts_serde, FileData, TextCounts};
+use crate::heavy_hitters::TopWords;
+use crate::options::OutputOptions;
+use crate::sketch::{DistinctSketch, LengthSketch};
+use crate::terms::TermScore;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::{self, Read, Write};
 
 /// Struct representing the JSON result containing extracted data.
 ///
 /// This struct represents the JSON result containing information about the extracted data,
 /// including a padron identifier, a vector of site data (`FileData`), tag counts,
-/// and totals of chatty sites and tags.
+/// the question length distributions, the words by text role and the pairs of the tags,
+/// the distinctive terms and the distinct words of the tags and the clusters of near-duplicate
+/// questions, if they were collected, and totals of chatty sites and tags, top pairs of tags
+/// and most frequent words.
 ///
-#[derive(Debug, PartialEq)]
+/// In JSON, the sites are an object keyed by filename and the tag counts are
+/// objects with `questions` and `words` fields. A result with two sites of the same
+/// filename can't be written or read.
+///
+/// A result keeps the state needed to merge it with other results, such as the counts of
+/// its tag pairs and terms, until `clear_state` drops it to keep only the reports.
+///
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct ResultJson {
     pub padron: String,
+    #[serde(with = "sites_serde")]
     pub sites: Vec<FileData>,
+    #[serde(with = "tag_counts_serde")]
     pub tags: HashMap<String, (usize, usize)>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_lengths: HashMap<String, LengthSketch>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_texts: HashMap<String, TextCounts>,
+    #[serde(default, skip_serializing_if = "TagPairs::is_empty")]
+    pub tag_pairs: TagPairs,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_top_terms: HashMap<String, Vec<TermScore>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_distinct_words: HashMap<String, DistinctSketch>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_clusters: Vec<DuplicateCluster>,
     pub totals: Totals,
+    /// Whether the result keeps the state needed to merge it with others.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub state: bool,
 }
 
 /// Struct representing totals of chatty sites and tags.
@@ -20,64 +63,616 @@ pub struct ResultJson {
 /// This struct represents the totals of chatty sites and tags, which are part of the
 /// overall result containing extracted data.
 ///
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Totals {
     pub chatty_sites: Vec<String>,
     pub chatty_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_tag_pairs: Option<TopTagPairs>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_words: Option<TopWords>,
 }
 
 impl ResultJson {
+    /// Build the result of a set of processed sites.
+    ///
+    /// This function is equivalent to `from_sites_with` with the default ranking sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `padron`: The padron identifier of the result.
+    /// * `sites`: The data of every site, as returned by `process_files`.
+    ///
+    /// # Returns
+    ///
+    /// The `ResultJson` with the sites, the aggregated tag counts and the totals.
+    ///
+    pub fn from_sites(padron: String, sites: Vec<FileData>) -> Self {
+        Self::from_sites_with(padron, sites, &RankingOptions::default())
+    }
+
+    /// Build the result of a set of processed sites with the given ranking options.
+    ///
+    /// This function generates the top tags of every site, aggregates the tag counts,
+    /// question length distributions and words by text role of all the sites and computes
+    /// the chatty sites and tags, ranked by `ranking.metric`.
+    ///
+    /// # Arguments
+    ///
+    /// * `padron`: The padron identifier of the result.
+    /// * `sites`: The data of every site, as returned by `process_files`.
+    /// * `ranking`: The size, minimum number of questions and metric of each ranking.
+    ///
+    /// # Returns
+    ///
+    /// The `ResultJson` with the sites, the aggregated tag counts and the totals.
+    ///
+    pub fn from_sites_with(
+        padron: String,
+        mut sites: Vec<FileData>,
+        ranking: &RankingOptions,
+    ) -> Self {
+        generate_top_n_tags_by(
+            &mut sites,
+            ranking.top_site_tags,
+            ranking.min_questions_site_tags,
+            ranking.metric,
+        );
+        generate_top_n_tag_pairs(
+            &mut sites,
+            ranking.top_tag_pairs,
+            ranking.min_questions_tag_pairs,
+        );
+        generate_top_n_terms(&mut sites, ranking.top_terms);
+        generate_top_k_words(&mut sites, ranking.top_words);
+
+        let tags = aggregate_tag_counts(&sites);
+        let tag_lengths = aggregate_tag_lengths(&sites);
+        let tag_texts = aggregate_tag_texts(&sites);
+        let tag_pairs = aggregate_tag_pairs(&sites);
+        let tag_distinct_words = aggregate_tag_distinct_words(&sites);
+        let duplicate_clusters = aggregate_duplicate_clusters(&sites);
+        let tag_top_terms = top_n_tag_terms(&aggregate_tag_terms(&sites), ranking.top_terms);
+        let chatty_tags = top_n_tags_by(
+            &tags,
+            &tag_lengths,
+            ranking.top_tags,
+            ranking.min_questions_tags,
+            ranking.metric,
+        );
+        let chatty_sites = top_n_filenames_by(
+            &sites,
+            ranking.top_sites,
+            ranking.min_questions_sites,
+            ranking.metric,
+        );
+        let top_tag_pairs = (!tag_pairs.is_empty()).then(|| {
+            let questions = sites.iter().map(|site| site.total_line_count).sum();
+            top_n_tag_pairs(
+                &tag_pairs,
+                &tags,
+                questions,
+                ranking.top_tag_pairs,
+                ranking.min_questions_tag_pairs,
+            )
+        });
+
+        let top_words = aggregate_heavy_hitters(&sites)
+            .map(|heavy_hitters| heavy_hitters.top_k(ranking.top_words));
+
+        ResultJson {
+            padron,
+            sites,
+            tags,
+            tag_lengths,
+            tag_texts,
+            tag_pairs,
+            tag_top_terms,
+            tag_distinct_words,
+            duplicate_clusters,
+            totals: Totals {
+                chatty_sites,
+                chatty_tags,
+                top_tag_pairs,
+                top_words,
+            },
+            state: true,
+        }
+    }
+
+    /// Merge the results of independently processed shards.
+    ///
+    /// This function sums the counts of the sites with the same filename, then rebuilds
+    /// the aggregated tag counts, the top tags of every site and the chatty sites and tags
+    /// with `from_sites_with`. Merging the results of disjoint sets of files gives the same
+    /// result as processing all the files at once, as long as no result `lacks_state`. The
+    /// sites keep the order in which they first appear, and the padron identifier is taken
+    /// from the first result.
+    ///
+    /// # Arguments
+    ///
+    /// * `results`: The results to merge.
+    /// * `ranking`: The size, minimum number of questions and metric of each ranking of the
+    ///   merged result.
+    ///
+    /// # Returns
+    ///
+    /// The merged `ResultJson`.
+    ///
+    pub fn merge(results: Vec<ResultJson>, ranking: &RankingOptions) -> Self {
+        let padron = results
+            .first()
+            .map(|result| result.padron.clone())
+            .unwrap_or_default();
+
+        let mut sites: Vec<FileData> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for site in results.into_iter().flat_map(|result| result.sites) {
+            match positions.get(&site.filename) {
+                Some(&position) => sites[position].append(site),
+                None => {
+                    positions.insert(site.filename.clone(), sites.len());
+                    sites.push(site);
+                }
+            }
+        }
+
+        Self::from_sites_with(padron, sites, ranking)
+    }
+
+    /// Drop the state needed to merge the result with others, keeping only its reports.
+    ///
+    /// The counts of the tag pairs, the term counts and the heavy hitter counters are
+    /// dropped, and the distinct word sketches keep their word counts and estimates, but
+    /// not their registers.
+    ///
+    pub fn clear_state(&mut self) {
+        for site in &mut self.sites {
+            site.clear_state();
+        }
+        self.tag_pairs = TagPairs::default();
+        for distinct_words in self.tag_distinct_words.values_mut() {
+            distinct_words.drop_registers();
+        }
+        self.state = false;
+    }
+
+    /// Whether the result collected counts that can only be merged with its state, the
+    /// tag pairs, terms, heavy hitters or distinct words, but no longer keeps it.
+    ///
+    /// Results without those counts can always be merged, since their question and word
+    /// counts are exact.
+    ///
+    pub fn lacks_state(&self) -> bool {
+        if self.state {
+            return false;
+        }
+        let site_needs_state = |site: &FileData| {
+            site.top_tag_pairs.is_some()
+                || !site.top_terms.is_empty()
+                || site.distinct_words.is_some()
+                || site.top_words.is_some()
+        };
+        self.totals.top_tag_pairs.is_some()
+            || self.totals.top_words.is_some()
+            || !self.tag_top_terms.is_empty()
+            || !self.tag_distinct_words.is_empty()
+            || self.sites.iter().any(site_needs_state)
+    }
+
+    /// Read a result previously written by `print` or `write_json`.
+    ///
+    /// This function restores the sites, with their tag counts, chatty tags and skipped
+    /// lines, the aggregated tag counts and the totals of a JSON result. The order of
+    /// the sites is kept. Wrap files in a `BufReader`, since the reader is not buffered.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: The reader over the JSON document.
+    ///
+    /// # Returns
+    ///
+    /// The restored `ResultJson`, or `Error::Result` if the document is not a valid result.
+    ///
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(Error::Result)
+    }
+
     /// Print the JSON representation of the result.
     ///
     /// This function prints the JSON representation of the `ResultJson` struct to the standard output.
     /// It formats the data in a structured way, including padron identifier, site data, tag counts,
-    /// and totals of chatty sites and tags.
+    /// and totals of chatty sites and tags. Sites with skipped lines also report them.
     ///
     pub fn print(&self) {
-        println!("{{");
-        println!("  \"padron\": \"{}\",", self.padron);
-        println!("  \"sites\": {{");
-        for (site_index, site) in self.sites.iter().enumerate() {
-            println!("    \"{}\": {{", site.filename);
-            println!("      \"questions\": {},", site.total_line_count);
-            println!("      \"words\": {},", site.total_word_count);
-            println!("      \"tags\": {{");
-            for (tag_index, (tag, counts)) in site.tag_counts.iter().enumerate() {
-                println!("        \"{}\": {{", tag);
-                println!("          \"questions\": {},", counts.0);
-                println!("          \"words\": {}", counts.1);
-                if tag_index < site.tag_counts.len() - 1 {
-                    println!("        }},");
-                } else {
-                    println!("        }}");
+        let stdout = io::stdout();
+        if let Err(err) = self.write_json(&mut stdout.lock()) {
+            eprintln!("Failed to print result: {}", err);
+        }
+    }
+
+    /// Write the JSON representation of the result.
+    ///
+    /// This function writes the same JSON document produced by `print` to any writer.
+    /// Tag and file names are escaped, so the document is always valid JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer where the JSON document is written.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the document could be written.
+    ///
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_json_with(writer, &OutputOptions::default())
+    }
+
+    /// Write the JSON representation of the result with the given output options.
+    ///
+    /// With `sort_keys`, the document is first converted to a `serde_json::Value`, whose
+    /// objects keep their keys sorted, so the sites, the tags and the fields of every
+    /// object are written in alphabetical order. Either way, writing fails if two sites
+    /// share a filename, rather than leaving one of them out.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer where the JSON document is written.
+    /// * `options`: The options that control the layout of the document.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the document could be written.
+    ///
+    pub fn write_json_with<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &OutputOptions,
+    ) -> io::Result<()> {
+        if options.sort_keys {
+            let value = serde_json::to_value(self)?;
+            serde_json::to_writer_pretty(&mut *writer, &value)?;
+        } else {
+            serde_json::to_writer_pretty(&mut *writer, self)?;
+        }
+        writeln!(writer)
+    }
+
+    /// Write a human-readable summary of the result.
+    ///
+    /// This function writes the number of questions and words of every site together with
+    /// the rankings of chatty sites and tags, one entry per line, the lines skipped
+    /// in each site, if any, and the question lengths and words by text role of the sites,
+    /// if they were collected.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer where the summary is written.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the summary could be written.
+    ///
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_text_with(writer, &OutputOptions::default())
+    }
+
+    /// Write a human-readable summary of the result with the given output options.
+    ///
+    /// With `sort_keys`, the sites are listed by filename instead of in processing order.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: The writer where the summary is written.
+    /// * `options`: The options that control the layout of the summary.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the summary could be written.
+    ///
+    pub fn write_text_with<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &OutputOptions,
+    ) -> io::Result<()> {
+        let mut sites: Vec<&FileData> = self.sites.iter().collect();
+        if options.sort_keys {
+            sites.sort_by(|site1, site2| site1.filename.cmp(&site2.filename));
+        }
+
+        writeln!(writer, "Padron: {}", self.padron)?;
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "{:<40} {:>12} {:>14} {:>10}",
+            "Site", "Questions", "Words", "Ratio"
+        )?;
+        for site in &sites {
+            writeln!(
+                writer,
+                "{:<40} {:>12} {:>14} {:>10.2}",
+                site.filename,
+                site.total_line_count,
+                site.total_word_count,
+                words_questions_ratio(site)
+            )?;
+        }
+        if sites.iter().any(|site| site.skipped.total() > 0) {
+            writeln!(writer)?;
+            writeln!(writer, "Skipped lines:")?;
+            for site in sites.iter().filter(|site| site.skipped.total() > 0) {
+                writeln!(
+                    writer,
+                    "  {}: {} malformed, {} not an object, {} missing a field (first lines: {:?})",
+                    site.filename,
+                    site.skipped.malformed,
+                    site.skipped.non_object,
+                    site.skipped.missing_field,
+                    site.skipped.sample_lines
+                )?;
+            }
+        }
+        if sites.iter().any(|site| site.lengths.is_some()) {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "{:<40} {:>8} {:>10} {:>10} {:>10} {:>8}",
+                "Question lengths", "Min", "Median", "P90", "P99", "Max"
+            )?;
+            for site in &sites {
+                if let Some(stats) = site.lengths.as_ref().and_then(LengthSketch::stats) {
+                    writeln!(
+                        writer,
+                        "{:<40} {:>8} {:>10.1} {:>10.1} {:>10.1} {:>8}",
+                        site.filename, stats.min, stats.median, stats.p90, stats.p99, stats.max
+                    )?;
+                }
+            }
+        }
+        if sites.iter().any(|site| !site.texts.is_empty()) {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "{:<40} {:>14} {:>14} {:>14} {:>10}",
+                "Words by text", "Title", "Body", "Other", "Texts"
+            )?;
+            for site in sites.iter().filter(|site| !site.texts.is_empty()) {
+                writeln!(
+                    writer,
+                    "{:<40} {:>14} {:>14} {:>14} {:>10}",
+                    site.filename,
+                    site.texts.title_words,
+                    site.texts.body_words,
+                    site.texts.other_words,
+                    site.texts.entries
+                )?;
+            }
+        }
+        if sites.iter().any(|site| site.code_word_count > 0) {
+            writeln!(writer)?;
+            writeln!(writer, "{:<40} {:>14}", "Code words", "Words")?;
+            for site in sites.iter().filter(|site| site.code_word_count > 0) {
+                writeln!(writer, "{:<40} {:>14}", site.filename, site.code_word_count)?;
+            }
+        }
+        if sites.iter().any(|site| site.distinct_words.is_some()) {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "{:<40} {:>14} {:>14} {:>10}",
+                "Vocabulary", "Words", "Distinct", "Diversity"
+            )?;
+            for site in &sites {
+                if let Some(distinct_words) = &site.distinct_words {
+                    writeln!(
+                        writer,
+                        "{:<40} {:>14} {:>14} {:>10.3}",
+                        site.filename,
+                        distinct_words.words(),
+                        distinct_words.estimate(),
+                        distinct_words.diversity().unwrap_or(0.0)
+                    )?;
                 }
             }
-            println!("      }},");
-            println!("      \"chatty_tags\": {:?}", site.top_tags);
-            if site_index < self.sites.len() - 1 {
-                println!("    }},");
-            } else {
-                println!("    }}");
+        }
+        if sites
+            .iter()
+            .any(|site| !site.duplicates.is_empty() || site.excluded_duplicate_count > 0)
+        {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "{:<40} {:>12} {:>12}",
+                "Near-duplicates", "Duplicates", "Excluded"
+            )?;
+            for site in &sites {
+                if !site.duplicates.is_empty() || site.excluded_duplicate_count > 0 {
+                    writeln!(
+                        writer,
+                        "{:<40} {:>12} {:>12}",
+                        site.filename,
+                        site.duplicates.len(),
+                        site.excluded_duplicate_count
+                    )?;
+                }
+            }
+        }
+        if sites.iter().any(|site| !site.top_terms.is_empty()) {
+            writeln!(writer)?;
+            writeln!(writer, "Distinctive terms:")?;
+            for site in sites.iter().filter(|site| !site.top_terms.is_empty()) {
+                writeln!(
+                    writer,
+                    "  {}: {}",
+                    site.filename,
+                    term_list(&site.top_terms)
+                )?;
             }
         }
+        writeln!(writer)?;
+        writeln!(writer, "Chatty sites:")?;
+        for (position, site) in self.totals.chatty_sites.iter().enumerate() {
+            writeln!(writer, "  {:>2}. {}", position + 1, site)?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "Chatty tags:")?;
+        for (position, tag) in self.totals.chatty_tags.iter().enumerate() {
+            match self.tag_top_terms.get(tag) {
+                Some(top_terms) => writeln!(
+                    writer,
+                    "  {:>2}. {} ({})",
+                    position + 1,
+                    tag,
+                    term_list(top_terms)
+                )?,
+                None => writeln!(writer, "  {:>2}. {}", position + 1, tag)?,
+            }
+        }
+        if let Some(top_words) = &self.totals.top_words {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "Top words (of {} words, counts at most {} below the true count):",
+                top_words.words, top_words.max_error
+            )?;
+            for (position, word) in top_words.top.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "  {:>2}. {} ({}..{})",
+                    position + 1,
+                    word.word,
+                    word.count,
+                    word.max_count
+                )?;
+            }
+        }
+        if let Some(top_tag_pairs) = &self.totals.top_tag_pairs {
+            write_tag_pairs(
+                writer,
+                "Top tag pairs by questions:",
+                &top_tag_pairs.by_count,
+            )?;
+            write_tag_pairs(writer, "Top tag pairs by PMI:", &top_tag_pairs.by_pmi)?;
+        }
+        if !self.duplicate_clusters.is_empty() {
+            let across_sites = self
+                .duplicate_clusters
+                .iter()
+                .filter(|cluster| cluster.across_sites)
+                .count();
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "Near-duplicate clusters ({} clusters, {} across sites):",
+                self.duplicate_clusters.len(),
+                across_sites
+            )?;
+            for (position, cluster) in self
+                .duplicate_clusters
+                .iter()
+                .take(DUPLICATE_CLUSTER_SAMPLE_SIZE)
+                .enumerate()
+            {
+                let questions: Vec<String> = cluster
+                    .questions
+                    .iter()
+                    .map(|question| format!("{}:{}", question.site, question.line))
+                    .collect();
+                writeln!(writer, "  {:>2}. {}", position + 1, questions.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Join distinctive terms into a comma-separated list.
+fn term_list(terms: &[TermScore]) -> String {
+    terms
+        .iter()
+        .map(|term| term.term.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-        println!("  }},");
-        println!("  \"tags\": {{");
-        for (index, (tag, counts)) in self.tags.iter().enumerate() {
-            println!("    \"{}\": {{", tag);
-            println!("      \"questions\": {},", counts.0);
-            println!("      \"words\": {}", counts.1);
-            if index < self.tags.len() - 1 {
-                println!("    }},");
-            } else {
-                println!("    }}");
+/// Write a ranking of pairs of tags with its counts, PMI and lift.
+fn write_tag_pairs<W: Write>(
+    writer: &mut W,
+    title: &str,
+    pairs: &[TagPairStats],
+) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "{}", title)?;
+    for (position, pair) in pairs.iter().enumerate() {
+        writeln!(
+            writer,
+            "  {:>2}. {} + {} ({} questions, PMI {:.2}, lift {:.2})",
+            position + 1,
+            pair.tags.0,
+            pair.tags.1,
+            pair.questions,
+            pair.pmi,
+            pair.lift
+        )?;
+    }
+    Ok(())
+}
+
+/// Serialization of the sites as an object keyed by filename.
+///
+/// The order of the sites is kept in both directions, and the filename of each
+/// `FileData` is restored from its key when deserializing.
+///
+mod sites_serde {
+    use crate::file_data::FileData;
+    use serde::de::{Error, MapAccess, Visitor};
+    use serde::ser::Error as _;
+    use serde::{Deserializer, Serializer};
+    use std::collections::HashSet;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(sites: &[FileData], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut filenames = HashSet::new();
+        if let Some(site) = sites
+            .iter()
+            .find(|site| !filenames.insert(site.filename.as_str()))
+        {
+            return Err(S::Error::custom(format!(
+                "more than one site is named {}",
+                site.filename
+            )));
+        }
+        serializer.collect_map(sites.iter().map(|site| (&site.filename, site)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<FileData>, D::Error> {
+        deserializer.deserialize_map(SitesVisitor)
+    }
+
+    struct SitesVisitor;
+
+    impl<'de> Visitor<'de> for SitesVisitor {
+        type Value = Vec<FileData>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an object mapping filenames to sites")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut sites = Vec::with_capacity(map.size_hint().unwrap_or(0));
+            let mut filenames = HashSet::new();
+            while let Some((filename, mut site)) = map.next_entry::<String, FileData>()? {
+                if !filenames.insert(filename.clone()) {
+                    return Err(A::Error::custom(format!(
+                        "more than one site is named {}",
+                        filename
+                    )));
+                }
+                site.filename = filename;
+                sites.push(site);
             }
+            Ok(sites)
         }
-        println!("  }},");
-        println!("  \"totals\": {{");
-        println!("    \"chatty_sites\": {:?},", self.totals.chatty_sites);
-        println!("    \"chatty_tags\": {:?}", self.totals.chatty_tags);
-        println!("  }}");
-        println!("}}");
     }
 }
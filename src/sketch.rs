@@ -0,0 +1,596 @@
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// Relative accuracy of the quantiles estimated by `LengthSketch`.
+pub const SKETCH_RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Default precision of `DistinctSketch`, which uses `2^precision` registers.
+pub const DEFAULT_DISTINCT_PRECISION: u8 = 10;
+
+/// Smallest and largest precision of `DistinctSketch`.
+pub const DISTINCT_PRECISION_RANGE: std::ops::RangeInclusive<u8> = 4..=16;
+
+/// Struct representing the distribution of the lengths of a set of questions.
+///
+/// Lengths are counted in logarithmic buckets, so every quantile is estimated within
+/// `SKETCH_RELATIVE_ACCURACY` of a length of the set, and the number of buckets grows
+/// with the logarithm of the longest question instead of with the number of questions.
+/// The minimum and maximum lengths are exact. Sketches are merged by adding their
+/// buckets, so the sketch of a file does not depend on how its lines were split
+/// between tasks.
+///
+/// In JSON, the buckets are written together with the `LengthStats` of the sketch,
+/// which are computed again from the buckets when it is read.
+///
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct LengthSketch {
+    count: u64,
+    #[serde(default)]
+    min: usize,
+    #[serde(default)]
+    max: usize,
+    zeros: u64,
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl LengthSketch {
+    /// Add the length of a question to the distribution.
+    pub fn insert(&mut self, length: usize) {
+        if self.count == 0 {
+            self.min = length;
+            self.max = length;
+        } else {
+            self.min = self.min.min(length);
+            self.max = self.max.max(length);
+        }
+        self.count += 1;
+
+        if length == 0 {
+            self.zeros += 1;
+        } else {
+            *self.buckets.entry(bucket_index(length)).or_insert(0) += 1;
+        }
+    }
+
+    /// Add the lengths of another sketch to this one.
+    pub fn merge(&mut self, other: &LengthSketch) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.count += other.count;
+        self.zeros += other.zeros;
+        for (&index, &count) in &other.buckets {
+            *self.buckets.entry(index).or_insert(0) += count;
+        }
+    }
+
+    /// Number of lengths in the distribution.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Shortest length, if any.
+    pub fn min(&self) -> Option<usize> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Longest length, if any.
+    pub fn max(&self) -> Option<usize> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Estimate a quantile of the distribution.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantile`: The quantile to estimate, between 0 and 1.
+    ///
+    /// # Returns
+    ///
+    /// The estimated length, or `None` if the distribution is empty.
+    ///
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (quantile.clamp(0.0, 1.0) * (self.count - 1) as f64).floor() as u64;
+        if rank == 0 {
+            return Some(self.min as f64);
+        }
+        if rank == self.count - 1 {
+            return Some(self.max as f64);
+        }
+
+        let mut seen = self.zeros;
+        if rank < seen {
+            return Some(0.0);
+        }
+        for (&index, &count) in &self.buckets {
+            seen += count;
+            if rank < seen {
+                let length = bucket_length(index).clamp(self.min as f64, self.max as f64);
+                return Some(length);
+            }
+        }
+        Some(self.max as f64)
+    }
+
+    /// Summarize the distribution.
+    ///
+    /// # Returns
+    ///
+    /// The minimum, maximum, median, 90th and 99th percentile lengths, or `None` if the
+    /// distribution is empty.
+    ///
+    pub fn stats(&self) -> Option<LengthStats> {
+        Some(LengthStats {
+            min: self.min()?,
+            max: self.max()?,
+            median: self.quantile(0.5)?,
+            p90: self.quantile(0.9)?,
+            p99: self.quantile(0.99)?,
+        })
+    }
+}
+
+/// Struct representing the summary of a length distribution.
+///
+/// The minimum and maximum are exact, while the quantiles are estimated within
+/// `SKETCH_RELATIVE_ACCURACY`.
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LengthStats {
+    pub min: usize,
+    pub max: usize,
+    pub median: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Serialize for LengthSketch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct StoredSketch<'a> {
+            count: u64,
+            #[serde(flatten)]
+            stats: Option<LengthStats>,
+            zeros: u64,
+            buckets: &'a BTreeMap<i32, u64>,
+        }
+
+        StoredSketch {
+            count: self.count,
+            stats: self.stats(),
+            zeros: self.zeros,
+            buckets: &self.buckets,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Growth factor between the bounds of consecutive buckets.
+fn gamma() -> f64 {
+    (1.0 + SKETCH_RELATIVE_ACCURACY) / (1.0 - SKETCH_RELATIVE_ACCURACY)
+}
+
+/// Index of the bucket holding a positive length.
+fn bucket_index(length: usize) -> i32 {
+    ((length as f64).ln() / gamma().ln()).ceil() as i32
+}
+
+/// Length that represents a bucket, within the relative accuracy of all its lengths.
+fn bucket_length(index: i32) -> f64 {
+    let gamma = gamma();
+    2.0 * gamma.powi(index) / (gamma + 1.0)
+}
+
+/// Struct representing the distinct words of a set of questions, as a HyperLogLog sketch.
+///
+/// Every word is hashed into one of `2^precision` registers, which keeps the longest run
+/// of leading zeros of the hashes it received. The number of distinct words is estimated
+/// from the registers with a relative standard error of about `1.04 / sqrt(2^precision)`,
+/// 3.25% with the default precision. Sketches are merged by keeping the largest value of
+/// every register, so the sketch of a file does not depend on how its lines were split
+/// between tasks. Sketches of different precisions are merged at the lower precision.
+///
+/// While at most a quarter of the registers are set, only those are kept, sorted by
+/// index, so the sketches of small sites and tags take a few bytes; past that, every
+/// register is kept, one byte each, no matter how many words the sketch saw.
+///
+/// The sketch also counts every word it received, to report the lexical diversity of
+/// the questions: the share of their words that are distinct.
+///
+/// In JSON, the sketch is written with its word count, its estimated distinct words and
+/// lexical diversity. Unless they were dropped with `drop_registers`, its precision and
+/// its registers, which are needed to merge it, are written too: the registers that are
+/// set as `[index, value]` pairs while they are few, and every register as a hexadecimal
+/// string otherwise.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DistinctSketch {
+    precision: u8,
+    words: u64,
+    registers: Registers,
+}
+
+/// Registers of a `DistinctSketch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Registers {
+    /// The registers that are set, by index.
+    Sparse(Vec<(u16, u8)>),
+    /// Every register.
+    Dense(Vec<u8>),
+    /// No register, only the estimated distinct words of a sketch whose registers were
+    /// dropped.
+    Dropped(u64),
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers::Sparse(Vec::new())
+    }
+}
+
+impl DistinctSketch {
+    /// Create an empty sketch with `2^precision` registers.
+    ///
+    /// # Panics
+    ///
+    /// If `precision` is not in `DISTINCT_PRECISION_RANGE`.
+    ///
+    pub fn new(precision: u8) -> Self {
+        assert!(
+            DISTINCT_PRECISION_RANGE.contains(&precision),
+            "the precision of a distinct sketch must be between 4 and 16"
+        );
+        DistinctSketch {
+            precision,
+            ..Default::default()
+        }
+    }
+
+    /// Hash a word, so it can be added to several sketches with `insert_hash`.
+    pub fn hash_word(word: &str) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in word.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        mix(hash)
+    }
+
+    /// Add a word to the sketch.
+    pub fn insert(&mut self, word: &str) {
+        self.insert_hash(Self::hash_word(word));
+    }
+
+    /// Add a word, given its `hash_word`, to the sketch.
+    pub fn insert_hash(&mut self, hash: u64) {
+        self.words += 1;
+        if self.precision == 0 {
+            return;
+        }
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rank =
+            ((hash << self.precision) | (1 << (self.precision - 1))).leading_zeros() as u8 + 1;
+        self.update(index, rank);
+    }
+
+    /// Add the words of another sketch to this one.
+    ///
+    /// Sketches whose registers were dropped can only add their estimates, which bound
+    /// the distinct words of the merged sketch from above.
+    ///
+    pub fn merge(&mut self, other: &DistinctSketch) {
+        if self.is_dropped() || other.is_dropped() {
+            let estimate = self.estimate() + other.estimate();
+            self.words += other.words;
+            self.precision = 0;
+            self.registers = Registers::Dropped(estimate);
+            return;
+        }
+        self.words += other.words;
+        if other.precision == 0 {
+            return;
+        }
+        if self.precision == 0 {
+            self.precision = other.precision;
+            self.registers = other.registers.clone();
+            return;
+        }
+        if other.precision < self.precision {
+            *self = self.with_precision(other.precision);
+        }
+        let folded;
+        let other = if other.precision > self.precision {
+            folded = other.with_precision(self.precision);
+            &folded
+        } else {
+            other
+        };
+        for (index, value) in other.set_registers() {
+            self.update(index, value);
+        }
+    }
+
+    /// Drop the registers, keeping only the word count and the estimated distinct words,
+    /// for a sketch that is only reported and not merged again.
+    pub fn drop_registers(&mut self) {
+        self.registers = Registers::Dropped(self.estimate());
+        self.precision = 0;
+    }
+
+    /// Number of words added to the sketch, counting repeated words every time.
+    pub fn words(&self) -> u64 {
+        self.words
+    }
+
+    /// Estimated number of distinct words.
+    pub fn estimate(&self) -> u64 {
+        if let Registers::Dropped(estimate) = self.registers {
+            return estimate;
+        }
+        if self.precision == 0 {
+            return 0;
+        }
+        let registers = (1usize << self.precision) as f64;
+        let alpha = match self.precision {
+            4 => 0.673,
+            5 => 0.697,
+            6 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / registers),
+        };
+        let (set, sum) = self
+            .set_registers()
+            .fold((0, 0.0), |(set, sum), (_, value)| {
+                (set + 1, sum + 2f64.powi(-(value as i32)))
+            });
+        let zeros = (1usize << self.precision) - set;
+        let estimate = alpha * registers * registers / (sum + zeros as f64);
+
+        let estimate = if estimate <= 2.5 * registers && zeros > 0 {
+            registers * (registers / zeros as f64).ln()
+        } else {
+            estimate
+        };
+        (estimate.round() as u64).min(self.words)
+    }
+
+    /// Estimated share of the words that are distinct, or `None` if there are no words.
+    pub fn diversity(&self) -> Option<f64> {
+        (self.words > 0).then(|| self.estimate() as f64 / self.words as f64)
+    }
+
+    /// Whether the registers of the sketch were dropped.
+    fn is_dropped(&self) -> bool {
+        matches!(self.registers, Registers::Dropped(_))
+    }
+
+    /// Iterate over the indexes and values of the registers that are set.
+    fn set_registers(&self) -> Box<dyn Iterator<Item = (usize, u8)> + '_> {
+        match &self.registers {
+            Registers::Sparse(registers) => Box::new(
+                registers
+                    .iter()
+                    .map(|&(index, value)| (index as usize, value)),
+            ),
+            Registers::Dense(registers) => Box::new(
+                registers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &value)| value > 0)
+                    .map(|(index, &value)| (index, value)),
+            ),
+            Registers::Dropped(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Largest number of registers that are kept sparse.
+    fn sparse_limit(&self) -> usize {
+        (1usize << self.precision) / 4
+    }
+
+    /// Raise a register to at least `value`, keeping every register once more than
+    /// `sparse_limit` are set.
+    fn update(&mut self, index: usize, value: u8) {
+        let limit = self.sparse_limit();
+        let dense = match &mut self.registers {
+            Registers::Dense(registers) => {
+                registers[index] = registers[index].max(value);
+                false
+            }
+            Registers::Sparse(registers) => {
+                match registers.binary_search_by_key(&(index as u16), |&(index, _)| index) {
+                    Ok(position) => registers[position].1 = registers[position].1.max(value),
+                    Err(position) => registers.insert(position, (index as u16, value)),
+                }
+                registers.len() > limit
+            }
+            Registers::Dropped(_) => false,
+        };
+        if dense {
+            self.registers = Registers::Dense(self.dense_registers());
+        }
+    }
+
+    /// Every register of the sketch, set or not.
+    fn dense_registers(&self) -> Vec<u8> {
+        let mut registers = vec![0; 1 << self.precision];
+        for (index, value) in self.set_registers() {
+            registers[index] = value;
+        }
+        registers
+    }
+
+    /// Keep the registers sparse if at most `sparse_limit` are set, and dense otherwise.
+    fn normalize(&mut self) {
+        let (set, dense) = match &self.registers {
+            Registers::Sparse(registers) => (registers.len(), false),
+            Registers::Dense(registers) => {
+                (registers.iter().filter(|&&value| value > 0).count(), true)
+            }
+            Registers::Dropped(_) => return,
+        };
+        if set > self.sparse_limit() && !dense {
+            self.registers = Registers::Dense(self.dense_registers());
+        } else if set <= self.sparse_limit() && dense {
+            let registers = self
+                .set_registers()
+                .map(|(index, value)| (index as u16, value))
+                .collect();
+            self.registers = Registers::Sparse(registers);
+        }
+    }
+
+    /// Copy of the sketch with a lower or equal precision.
+    fn with_precision(&self, precision: u8) -> DistinctSketch {
+        if precision == self.precision {
+            return self.clone();
+        }
+        let shift = self.precision - precision;
+        let mut folded = DistinctSketch::new(precision);
+        folded.words = self.words;
+        for (index, value) in self.set_registers() {
+            let dropped = index & ((1 << shift) - 1);
+            let rank = if dropped == 0 {
+                value + shift
+            } else {
+                (dropped.leading_zeros() - (usize::BITS - shift as u32)) as u8 + 1
+            };
+            folded.update(index >> shift, rank);
+        }
+        folded
+    }
+}
+
+/// Registers of a `DistinctSketch` in JSON.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredRegisters {
+    Sparse(Vec<(u16, u8)>),
+    Dense(String),
+}
+
+impl Serialize for DistinctSketch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct StoredSketch {
+            words: u64,
+            distinct: u64,
+            diversity: Option<f64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            precision: Option<u8>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            registers: Option<StoredRegisters>,
+        }
+
+        let registers = match &self.registers {
+            Registers::Sparse(registers) => Some(StoredRegisters::Sparse(registers.clone())),
+            Registers::Dense(registers) => Some(StoredRegisters::Dense(
+                registers
+                    .iter()
+                    .map(|value| format!("{:02x}", value))
+                    .collect(),
+            )),
+            Registers::Dropped(_) => None,
+        };
+        StoredSketch {
+            words: self.words,
+            distinct: self.estimate(),
+            diversity: self.diversity(),
+            precision: registers.as_ref().map(|_| self.precision),
+            registers,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DistinctSketch {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct StoredSketch {
+            words: u64,
+            #[serde(default)]
+            distinct: u64,
+            #[serde(default)]
+            precision: u8,
+            registers: Option<StoredRegisters>,
+        }
+
+        let stored = StoredSketch::deserialize(deserializer)?;
+        let registers = match stored.registers {
+            None => {
+                return Ok(DistinctSketch {
+                    precision: 0,
+                    words: stored.words,
+                    registers: Registers::Dropped(stored.distinct.min(stored.words)),
+                })
+            }
+            Some(StoredRegisters::Sparse(registers)) if stored.precision == 0 => {
+                if !registers.is_empty() {
+                    return Err(D::Error::custom("invalid distinct sketch precision"));
+                }
+                return Ok(DistinctSketch {
+                    words: stored.words,
+                    ..Default::default()
+                });
+            }
+            _ if !DISTINCT_PRECISION_RANGE.contains(&stored.precision) => {
+                return Err(D::Error::custom("invalid distinct sketch precision"));
+            }
+            Some(StoredRegisters::Sparse(registers)) => {
+                let increasing = registers.windows(2).all(|pair| pair[0].0 < pair[1].0);
+                let in_range = registers
+                    .iter()
+                    .all(|&(index, _)| (index as usize) < 1 << stored.precision);
+                if !increasing || !in_range {
+                    return Err(D::Error::custom("invalid distinct sketch registers"));
+                }
+                Registers::Sparse(registers)
+            }
+            Some(StoredRegisters::Dense(hex)) => {
+                let registers = (0..hex.len())
+                    .step_by(2)
+                    .map(|start| {
+                        hex.get(start..start + 2)
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| D::Error::custom("invalid distinct sketch registers"))?;
+                if registers.len() != 1 << stored.precision {
+                    return Err(D::Error::custom("invalid distinct sketch precision"));
+                }
+                Registers::Dense(registers)
+            }
+        };
+
+        let mut sketch = DistinctSketch {
+            precision: stored.precision,
+            words: stored.words,
+            registers,
+        };
+        let max_rank = 65 - stored.precision;
+        if sketch.set_registers().any(|(_, value)| value > max_rank) {
+            return Err(D::Error::custom("invalid distinct sketch registers"));
+        }
+        sketch.normalize();
+        Ok(sketch)
+    }
+}
+
+/// Spread the bits of a hash, so every bit depends on every bit of the input.
+pub(crate) fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
@@ -0,0 +1,198 @@
+use crate::top_k::par_top_k;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Default maximum number of distinct terms kept for each site and tag.
+pub const DEFAULT_MAX_TERMS: usize = 10_000;
+
+/// Common English words, which say nothing about the topic of a question.
+pub const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "before", "but", "by", "can", "could", "did", "do", "does", "doing",
+    "don't", "for", "from", "get", "had", "has", "have", "how", "i", "i'm", "if", "in", "into",
+    "is", "it", "it's", "its", "just", "like", "me", "more", "my", "no", "not", "of", "on", "one",
+    "only", "or", "other", "our", "out", "so", "some", "such", "than", "that", "the", "their",
+    "them", "then", "there", "these", "they", "this", "those", "to", "up", "use", "using", "was",
+    "we", "were", "what", "when", "where", "which", "while", "who", "why", "will", "with", "would",
+    "you", "your",
+];
+
+/// Common Spanish words, which say nothing about the topic of a question.
+pub const SPANISH_STOPWORDS: &[&str] = &[
+    "a", "al", "algo", "como", "con", "cuando", "de", "del", "desde", "donde", "el", "ella", "en",
+    "entre", "era", "es", "esa", "ese", "eso", "esta", "este", "esto", "está", "fue", "ha", "hay",
+    "la", "las", "le", "lo", "los", "me", "mi", "muy", "más", "no", "nos", "o", "para", "pero",
+    "por", "porque", "que", "qué", "se", "si", "sin", "sobre", "son", "su", "sus", "también", "te",
+    "tiene", "todo", "un", "una", "uno", "y", "ya", "yo", "cómo",
+];
+
+/// Struct representing how many times each term appears in a set of questions.
+///
+/// Terms are the lowercase words of the texts. Terms are counted exactly, and counts are
+/// merged by adding them, until they are pruned: when there are more than twice
+/// `max_terms` terms, only the `max_terms` most frequent are kept. A term dropped by a
+/// prune starts again from zero if it appears later, so once pruned the counts are
+/// approximate, lower than the true ones, and a term frequent overall may be missing.
+/// Like `HeavyHitters`, the counts are only pruned between merges done in a fixed order,
+/// such as once per batch of lines and after merging the batches of a file in order, so
+/// they do not depend on how the questions were split between threads.
+///
+/// In JSON, the terms are an object mapping every term to its count.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TermCounts {
+    counts: HashMap<String, u64>,
+}
+
+impl TermCounts {
+    /// Count an occurrence of a term.
+    pub fn insert(&mut self, term: &str) {
+        match self.counts.get_mut(term) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(term.to_string(), 1);
+            }
+        }
+    }
+
+    /// Add the counts of another `TermCounts` to this one.
+    pub fn merge(&mut self, other: &TermCounts) {
+        for (term, &count) in &other.counts {
+            *self.counts.entry(term.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Keep only the `max_terms` most frequent terms once there are more than twice
+    /// `max_terms` of them, so pruning after every merge takes amortized constant time.
+    pub fn prune(&mut self, max_terms: usize) {
+        if max_terms != 0 && self.counts.len() > 2 * max_terms {
+            self.truncate(max_terms);
+        }
+    }
+
+    /// Keep only the `max_terms` most frequent terms, or every term if it is zero.
+    ///
+    /// Terms with the same count are kept by name, so the terms kept only depend on the
+    /// counts.
+    ///
+    pub fn truncate(&mut self, max_terms: usize) {
+        if max_terms == 0 || self.counts.len() <= max_terms {
+            return;
+        }
+        let mut entries: Vec<(String, u64)> = self.counts.drain().collect();
+        entries.select_nth_unstable_by(max_terms - 1, |(term1, count1), (term2, count2)| {
+            count2.cmp(count1).then_with(|| term1.cmp(term2))
+        });
+        entries.truncate(max_terms);
+        self.counts = entries.into_iter().collect();
+    }
+
+    /// Number of times a term was counted.
+    pub fn count(&self, term: &str) -> u64 {
+        self.counts.get(term).copied().unwrap_or(0)
+    }
+
+    /// Total number of occurrences of all the terms.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Number of distinct terms.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Whether no term was counted.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Iterate over the terms and their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.counts.iter().map(|(term, &count)| (term, count))
+    }
+}
+
+/// Struct representing a distinctive term of a site or tag.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TermScore {
+    pub term: String,
+    /// Number of times the term appears in the site or tag.
+    pub count: u64,
+    pub tf_idf: f64,
+}
+
+/// Find the most distinctive terms of each of a set of documents.
+///
+/// The term frequency of a term is its share of the terms of a document, and its inverse
+/// document frequency is `ln((1 + documents) / (1 + documents with the term)) + 1`, so
+/// the terms shared by every document still rank by their frequency. Terms with the same
+/// TF-IDF are ranked by name.
+///
+/// # Arguments
+///
+/// * `documents`: The term counts of every document, such as every site or every tag.
+/// * `n`: The number of terms to select for each document.
+///
+/// # Returns
+///
+/// The top `n` `TermScore`s of every document, in the order of `documents`.
+///
+pub fn top_n_terms_tf_idf(documents: &[&TermCounts], n: usize) -> Vec<Vec<TermScore>> {
+    let document_frequencies = documents
+        .par_iter()
+        .fold(HashMap::new, |mut acc: HashMap<&str, usize>, terms| {
+            for (term, _) in terms.iter() {
+                *acc.entry(term.as_str()).or_insert(0) += 1;
+            }
+            acc
+        })
+        .reduce(HashMap::new, |mut acc, map| {
+            for (term, frequency) in map {
+                *acc.entry(term).or_insert(0) += frequency;
+            }
+            acc
+        });
+    let idf = |term: &str| {
+        let frequency = document_frequencies.get(term).copied().unwrap_or(0);
+        ((1 + documents.len()) as f64 / (1 + frequency) as f64).ln() + 1.0
+    };
+
+    documents
+        .par_iter()
+        .map(|terms| {
+            let total = terms.total() as f64;
+            let scores = terms
+                .counts
+                .par_iter()
+                .map(|(term, &count)| (term, count as f64 / total * idf(term)));
+            par_top_k(scores, n)
+                .into_iter()
+                .map(|term| {
+                    let count = terms.count(term);
+                    TermScore {
+                        term: term.clone(),
+                        count,
+                        tf_idf: count as f64 / total * idf(term),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether a word is a term, that is, it has a letter and it is not a stopword.
+pub fn is_term(word: &str, stopwords: &HashSet<String>) -> bool {
+    word.chars().any(char::is_alphabetic) && !stopwords.contains(word)
+}
+
+/// Build a set of stopwords from the given lists.
+pub fn stopword_set(lists: &[&[&str]]) -> HashSet<String> {
+    lists
+        .iter()
+        .flat_map(|list| list.iter())
+        .map(|word| word.to_string())
+        .collect()
+}
@@ -0,0 +1,93 @@
+use crate::normalize::{entity_end, tag_end};
+use std::fmt::Debug;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits the texts of the questions into the words that are counted.
+///
+/// Tokenizers are shared by every task processing the input, so they must be
+/// `Send` and `Sync`. The words are returned as slices of the text.
+///
+pub trait Tokenizer: Debug + Send + Sync {
+    /// Split a text into its words, in order.
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a>;
+
+    /// Count the words of a text.
+    fn count_words(&self, text: &str) -> usize {
+        self.tokens(text).count()
+    }
+}
+
+/// Tokenizer that splits texts at whitespace, as the program always did.
+///
+/// Punctuation stays attached to the words, so `foo,bar` is one word, and text in
+/// scripts that are not written with spaces, such as Chinese, is one word per run.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(text.split_whitespace())
+    }
+
+    fn count_words(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+/// Tokenizer that splits texts at the word boundaries of Unicode (UAX #29).
+///
+/// Punctuation and symbols are not words, so `foo,bar` is two words and markdown
+/// markers such as `**` or `#` are not counted, and every ideograph is a word.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnicodeWordTokenizer;
+
+impl Tokenizer for UnicodeWordTokenizer {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(text.unicode_words())
+    }
+}
+
+/// Tokenizer that leaves out HTML markup and splits the rest like `UnicodeWordTokenizer`.
+///
+/// HTML tags, such as `<p>` or `<a href="...">`, comments and character entities, such
+/// as `&amp;`, are not words. A `<` that does not start a tag, as in `a < b`, is kept.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkupTokenizer;
+
+impl Tokenizer for MarkupTokenizer {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(
+            markup_free_segments(text)
+                .into_iter()
+                .flat_map(|segment| segment.unicode_words()),
+        )
+    }
+}
+
+/// Split a text into the segments between its HTML tags and entities.
+fn markup_free_segments(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut position = 0;
+    while position < bytes.len() {
+        let markup_end = match bytes[position] {
+            b'<' => tag_end(bytes, position),
+            b'&' => entity_end(bytes, position),
+            _ => None,
+        };
+        match markup_end {
+            Some(end) => {
+                segments.push(&text[start..position]);
+                start = end;
+                position = end;
+            }
+            None => position += 1,
+        }
+    }
+    segments.push(&text[start..]);
+    segments
+}
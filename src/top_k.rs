@@ -0,0 +1,102 @@
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Select the `k` items with the highest score in parallel.
+///
+/// Every Rayon task keeps the best `k` items it has seen in a bounded min-heap, and the
+/// heaps of the tasks are merged in the reduce, so the work is `O(n log k)` and only `k`
+/// items are kept per task instead of collecting and sorting all of them. Items with the
+/// same score are ordered by the items themselves, so the selection does not depend on
+/// the order in which they are visited nor on the number of threads.
+///
+/// # Arguments
+///
+/// * `items`: A parallel iterator over the items and their scores.
+/// * `k`: The number of items to select.
+///
+/// # Returns
+///
+/// A vector with at most `k` items, sorted by decreasing score and then by increasing item.
+///
+pub fn par_top_k<T, I>(items: I, k: usize) -> Vec<T>
+where
+    T: Ord + Send,
+    I: ParallelIterator<Item = (T, f64)>,
+{
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let heap = items
+        .fold(BinaryHeap::new, |mut heap, (item, score)| {
+            push_bounded(&mut heap, Scored { score, item }, k);
+            heap
+        })
+        .reduce(BinaryHeap::new, |heap, other| merge_bounded(heap, other, k));
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|scored| scored.item)
+        .collect()
+}
+
+/// Item together with the score it is ranked by.
+///
+/// Greater means worse ranked: a lower score, or the same score and a greater item. So a
+/// `BinaryHeap` of `Scored` is a min-heap whose top is the worst ranked item.
+///
+struct Scored<T> {
+    score: f64,
+    item: T,
+}
+
+impl<T: Ord> PartialEq for Scored<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for Scored<T> {}
+
+impl<T: Ord> PartialOrd for Scored<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Scored<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .score
+            .total_cmp(&self.score)
+            .then_with(|| self.item.cmp(&other.item))
+    }
+}
+
+/// Push an item into a heap, dropping the worst ranked item if it holds more than `k` items.
+fn push_bounded<T: Ord>(heap: &mut BinaryHeap<Scored<T>>, scored: Scored<T>, k: usize) {
+    if heap.len() < k {
+        heap.push(scored);
+    } else if heap.peek().is_some_and(|worst| scored < *worst) {
+        heap.pop();
+        heap.push(scored);
+    }
+}
+
+/// Merge two bounded heaps, keeping the best `k` items of both.
+fn merge_bounded<T: Ord>(
+    heap: BinaryHeap<Scored<T>>,
+    other: BinaryHeap<Scored<T>>,
+    k: usize,
+) -> BinaryHeap<Scored<T>> {
+    let (mut larger, smaller) = if heap.len() >= other.len() {
+        (heap, other)
+    } else {
+        (other, heap)
+    };
+    for scored in smaller {
+        push_bounded(&mut larger, scored, k);
+    }
+    larger
+}
@@ -1,3 +1,6 @@
+use crate::file_data::TextCounts;
+use crate::options::TextRole;
+use crate::tokenizer::Tokenizer;
 use serde_json::Value;
 
 /// Count the total number of words in a collection of text values.
@@ -17,6 +20,46 @@ pub fn count_words(texts: &[Value]) -> usize {
     texts
         .iter()
         .flat_map(|text| text.as_str())
-        .map(|text| text.split_whitespace().count())
+        .map(count_text_words)
         .sum()
 }
+
+/// Count the words of a single text.
+pub fn count_text_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// Count the words of a collection of text values by the role of each text.
+///
+/// This function counts the words of every text with a tokenizer, adding them to the
+/// title, body or other words according to the role of its position in `texts`.
+///
+/// # Arguments
+///
+/// * `texts`: The texts of a question, in order.
+/// * `roles`: The role of each position of `texts`. Positions past its end are other texts.
+/// * `tokenizer`: How the texts are split into words.
+///
+/// # Returns
+///
+/// The `TextCounts` of a single question.
+///
+pub fn count_words_by_role(
+    texts: &[&str],
+    roles: &[TextRole],
+    tokenizer: &dyn Tokenizer,
+) -> TextCounts {
+    let mut counts = TextCounts {
+        entries: texts.len(),
+        ..Default::default()
+    };
+    for (position, text) in texts.iter().enumerate() {
+        let words = tokenizer.count_words(text);
+        match roles.get(position).unwrap_or(&TextRole::Other) {
+            TextRole::Title => counts.title_words += words,
+            TextRole::Body => counts.body_words += words,
+            TextRole::Other => counts.other_words += words,
+        }
+    }
+    counts
+}
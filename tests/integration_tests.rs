@@ -1,12 +1,59 @@
+#![allow(clippy::bool_assert_comparison, clippy::ptr_arg)]
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::path::PathBuf;
 
     use tp1::{
-        aggregate_tag_counts, generate_top_tags, process_files, top_10_filenames_highest_ratio,
-        top_10_tags_highest_ratio, FileData, ResultJson, Totals,
+        aggregate_tag_counts, check_site_names, generate_top_tags, process_files,
+        process_files_with, top_10_filenames_highest_ratio, top_10_tags_highest_ratio, Error,
+        FileData, OutputOptions, ProcessOptions, RankChange, RankingOptions, ResultDiff,
+        ResultJson, Totals,
     };
 
+    /// Run the program with the given arguments.
+    fn run_cli(args: &[&str]) -> std::process::Output {
+        std::process::Command::new(env!("CARGO_BIN_EXE_tp1"))
+            .args(args)
+            .output()
+            .expect("Failed to run the program")
+    }
+
+    /// Process the two sites of `tests/testfiles/2files` as separate shards, merge their
+    /// results and check that their output matches processing both sites at once, which is
+    /// returned.
+    fn assert_merge_matches_whole(options: &ProcessOptions) -> ResultJson {
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+        ];
+
+        let shards = files
+            .iter()
+            .map(|file| {
+                let files_data = process_files_with(vec![file.clone()], options)
+                    .expect("Failed to process files");
+                let shard = ResultJson::from_sites("102676".to_string(), files_data);
+                let mut output = Vec::new();
+                shard
+                    .write_json(&mut output)
+                    .expect("Failed to write result");
+                ResultJson::from_reader(output.as_slice()).expect("Failed to read result")
+            })
+            .collect();
+
+        let files_data = process_files_with(files, options).expect("Failed to process files");
+        let single_run = ResultJson::from_sites("102676".to_string(), files_data);
+        let merged = ResultJson::merge(shards, &RankingOptions::default());
+
+        assert_eq!(
+            serde_json::to_value(&merged).expect("Failed to serialize result"),
+            serde_json::to_value(&single_run).expect("Failed to serialize result")
+        );
+        single_run
+    }
+
     fn are_result_json_equal(result1: &ResultJson, result2: &ResultJson) -> bool {
         result1.padron == result2.padron
             && are_sites_equal(&result1.sites, &result2.sites)
@@ -47,6 +94,7 @@ mod tests {
             total_line_count: 3,
             tag_counts: site1_tag_counts,
             top_tags: site1_top_tags,
+            ..Default::default()
         };
 
         let mut site2_tag_counts = HashMap::new();
@@ -61,6 +109,7 @@ mod tests {
             total_line_count: 3,
             tag_counts: site2_tag_counts,
             top_tags: site2_top_tags,
+            ..Default::default()
         };
 
         let mut all_tags = HashMap::new();
@@ -82,7 +131,10 @@ mod tests {
                     String::from("tag2"),
                     String::from("tag3"),
                 ],
+                top_tag_pairs: None,
+                top_words: None,
             },
+            ..Default::default()
         };
 
         let directory: &str = "tests/testfiles/2files";
@@ -92,7 +144,7 @@ mod tests {
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("Failed to collect file paths");
 
-        let mut files_data = process_files(files);
+        let mut files_data = process_files(files).expect("Failed to process files");
 
         generate_top_tags(&mut files_data);
 
@@ -108,9 +160,641 @@ mod tests {
             totals: Totals {
                 chatty_sites: top_10_filenames,
                 chatty_tags: top_10_tags,
+                top_tag_pairs: None,
+                top_words: None,
             },
+            ..Default::default()
         };
 
         assert_eq!(true, are_result_json_equal(&result, &result2));
     }
+
+    #[test]
+    fn test02_write_json_escapes_names() {
+        let directory: &str = "tests/testfiles/escaping";
+        let files = std::fs::read_dir(directory)
+            .expect("Failed to open directory")
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()
+            .expect("Failed to collect file paths");
+
+        let mut files_data = process_files(files).expect("Failed to process files");
+
+        generate_top_tags(&mut files_data);
+
+        let aggregated_tag_counts = aggregate_tag_counts(&files_data);
+        let top_10_tags: Vec<String> = top_10_tags_highest_ratio(&aggregated_tag_counts);
+        let top_10_filenames = top_10_filenames_highest_ratio(&files_data);
+
+        let result = ResultJson {
+            padron: "102676".to_string(),
+            sites: files_data,
+            tags: aggregated_tag_counts,
+            totals: Totals {
+                chatty_sites: top_10_filenames,
+                chatty_tags: top_10_tags,
+                top_tag_pairs: None,
+                top_words: None,
+            },
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        result
+            .write_json(&mut output)
+            .expect("Failed to write result");
+        let value: serde_json::Value =
+            serde_json::from_slice(&output).expect("Result is not valid JSON");
+
+        for tag in [
+            "comillas\"",
+            "barra\\invertida",
+            "ñandú",
+            "tab\t",
+            "\u{1}control",
+        ] {
+            assert_eq!(value["tags"][tag]["questions"], 1);
+            assert!(value["totals"]["chatty_tags"]
+                .as_array()
+                .expect("chatty_tags is not an array")
+                .contains(&serde_json::Value::from(tag)));
+        }
+    }
+
+    #[test]
+    fn test03_result_round_trip() {
+        let directory: &str = "tests/testfiles/2files";
+        let files = std::fs::read_dir(directory)
+            .expect("Failed to open directory")
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()
+            .expect("Failed to collect file paths");
+
+        let mut files_data = process_files(files).expect("Failed to process files");
+
+        generate_top_tags(&mut files_data);
+
+        let aggregated_tag_counts = aggregate_tag_counts(&files_data);
+        let top_10_tags: Vec<String> = top_10_tags_highest_ratio(&aggregated_tag_counts);
+        let top_10_filenames = top_10_filenames_highest_ratio(&files_data);
+
+        let result = ResultJson {
+            padron: "102676".to_string(),
+            sites: files_data,
+            tags: aggregated_tag_counts,
+            totals: Totals {
+                chatty_sites: top_10_filenames,
+                chatty_tags: top_10_tags,
+                top_tag_pairs: None,
+                top_words: None,
+            },
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        result
+            .write_json(&mut output)
+            .expect("Failed to write result");
+        let restored = ResultJson::from_reader(output.as_slice()).expect("Failed to read result");
+
+        assert_eq!(restored, result);
+        assert!(ResultJson::from_reader("{\"padron\": 1}".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test04_merge_shards() {
+        let directory: &str = "tests/testfiles/2files";
+        let files = std::fs::read_dir(directory)
+            .expect("Failed to open directory")
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()
+            .expect("Failed to collect file paths");
+
+        let shards = files
+            .iter()
+            .map(|file| {
+                let files_data =
+                    process_files(vec![file.clone()]).expect("Failed to process files");
+                let shard = ResultJson::from_sites("102676".to_string(), files_data);
+                let mut output = Vec::new();
+                shard
+                    .write_json(&mut output)
+                    .expect("Failed to write result");
+                ResultJson::from_reader(output.as_slice()).expect("Failed to read result")
+            })
+            .collect();
+
+        let files_data = process_files(files).expect("Failed to process files");
+        let mut single_run = ResultJson::from_sites("102676".to_string(), files_data);
+        let mut merged = ResultJson::merge(shards, &RankingOptions::default());
+
+        single_run.sites.sort_by(|a, b| a.filename.cmp(&b.filename));
+        merged.sites.sort_by(|a, b| a.filename.cmp(&b.filename));
+        assert_eq!(
+            serde_json::to_value(&merged).expect("Failed to serialize result"),
+            serde_json::to_value(&single_run).expect("Failed to serialize result")
+        );
+    }
+
+    #[test]
+    fn test05_merge_sums_sites_with_the_same_name() {
+        let files = vec![PathBuf::from("tests/testfiles/1file/testfile.jsonl")];
+        let files_data = process_files(files).expect("Failed to process files");
+        let shard = ResultJson::from_sites("102676".to_string(), files_data);
+
+        let files = vec![PathBuf::from("tests/testfiles/2files/testfile.jsonl")];
+        let files_data = process_files(files).expect("Failed to process files");
+        let other_shard = ResultJson::from_sites("102676".to_string(), files_data);
+
+        let merged = ResultJson::merge(vec![shard, other_shard], &RankingOptions::default());
+
+        assert_eq!(merged.sites.len(), 1);
+        assert_eq!(merged.sites[0].total_line_count, 6);
+        assert_eq!(merged.sites[0].total_word_count, 30);
+        assert_eq!(merged.tags.get("tag1"), Some(&(4, 18)));
+        assert_eq!(merged.tags.get("tag2"), Some(&(4, 22)));
+        assert_eq!(
+            merged.totals.chatty_sites,
+            vec!["testfile.jsonl".to_string()]
+        );
+    }
+
+    #[test]
+    fn test06_diff_results() {
+        let files = vec![PathBuf::from("tests/testfiles/1file/testfile.jsonl")];
+        let files_data = process_files(files).expect("Failed to process files");
+        let old = ResultJson::from_sites("102676".to_string(), files_data);
+
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+        ];
+        let files_data = process_files(files).expect("Failed to process files");
+        let new = ResultJson::from_sites("102676".to_string(), files_data);
+
+        let result_diff = ResultDiff::between(&old, &new);
+
+        assert_eq!(result_diff.sites.added, vec!["testfile2.jsonl".to_string()]);
+        assert!(result_diff.sites.removed.is_empty());
+        assert!(result_diff.sites.changed.is_empty());
+        assert_eq!(result_diff.tags.added, vec!["tag3".to_string()]);
+
+        let tag1 = &result_diff.tags.changed[0];
+        assert_eq!(tag1.name, "tag1");
+        assert_eq!((tag1.old_questions, tag1.new_questions), (2, 3));
+        assert_eq!((tag1.questions_delta, tag1.words_delta), (1, 9));
+        assert_eq!(tag1.ratio_delta, 1.5);
+
+        assert_eq!(
+            result_diff.chatty_sites,
+            vec![
+                RankChange {
+                    name: "testfile2.jsonl".to_string(),
+                    old_rank: None,
+                    new_rank: Some(1),
+                },
+                RankChange {
+                    name: "testfile.jsonl".to_string(),
+                    old_rank: Some(1),
+                    new_rank: Some(2),
+                },
+            ]
+        );
+        assert!(ResultDiff::between(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test07_sorted_keys_output_is_reproducible() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+        ];
+        let options = OutputOptions { sort_keys: true };
+
+        let write_sorted = |files: Vec<PathBuf>| {
+            let files_data = process_files(files).expect("Failed to process files");
+            let result = ResultJson::from_sites("102676".to_string(), files_data);
+            let mut json = Vec::new();
+            result
+                .write_json_with(&mut json, &options)
+                .expect("Failed to write result");
+            let mut text = Vec::new();
+            result
+                .write_text_with(&mut text, &options)
+                .expect("Failed to write result");
+            (json, text)
+        };
+
+        let (json, text) = write_sorted(files.clone());
+        let (reversed_json, reversed_text) = write_sorted(files.into_iter().rev().collect());
+
+        assert_eq!(json, reversed_json);
+        assert_eq!(text, reversed_text);
+        let json = String::from_utf8(json).expect("Output is not UTF-8");
+        let first = json.find("\"testfile.jsonl\"").expect("Missing site");
+        let second = json.find("\"testfile2.jsonl\"").expect("Missing site");
+        assert!(first < second);
+    }
+
+    #[test]
+    fn test08_length_distributions_merge_across_shards() {
+        let single_run = assert_merge_matches_whole(&ProcessOptions {
+            collect_lengths: true,
+            ..Default::default()
+        });
+
+        let stats = single_run.tag_lengths["tag1"]
+            .stats()
+            .expect("Missing stats");
+        assert_eq!((stats.min, stats.max), (4, 9));
+        assert!((stats.median - 5.0).abs() <= 0.05);
+
+        let mut output = Vec::new();
+        single_run
+            .write_json(&mut output)
+            .expect("Failed to write result");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("Output is not valid JSON");
+        assert_eq!(json["sites"]["testfile2.jsonl"]["lengths"]["max"], 9);
+        assert!(json["tag_lengths"]["tag3"]["p99"].is_number());
+    }
+
+    #[test]
+    fn test09_tag_pairs_merge_across_shards() {
+        let single_run = assert_merge_matches_whole(&ProcessOptions {
+            collect_tag_pairs: true,
+            ..Default::default()
+        });
+
+        assert_eq!(single_run.tag_pairs.count("tag1", "tag2"), 1);
+        assert_eq!(single_run.tag_pairs.count("tag2", "tag3"), 1);
+
+        let top_tag_pairs = single_run
+            .totals
+            .top_tag_pairs
+            .as_ref()
+            .expect("Missing top tag pairs");
+        let by_pmi: Vec<&(String, String)> =
+            top_tag_pairs.by_pmi.iter().map(|pair| &pair.tags).collect();
+        assert_eq!(
+            by_pmi,
+            vec![
+                &("tag2".to_string(), "tag3".to_string()),
+                &("tag1".to_string(), "tag2".to_string())
+            ]
+        );
+        assert!((top_tag_pairs.by_pmi[0].lift - 1.0).abs() < 1e-9);
+
+        let mut output = Vec::new();
+        single_run
+            .write_json(&mut output)
+            .expect("Failed to write result");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("Output is not valid JSON");
+        assert_eq!(
+            json["tag_pairs"][0]["tags"],
+            serde_json::json!(["tag1", "tag2"])
+        );
+        assert_eq!(
+            json["sites"]["testfile.jsonl"]["top_tag_pairs"]["by_count"][0]["questions"],
+            1
+        );
+    }
+
+    #[test]
+    fn test10_distinctive_terms_merge_across_shards() {
+        let single_run = assert_merge_matches_whole(&ProcessOptions {
+            collect_terms: true,
+            ..Default::default()
+        });
+
+        assert_eq!(single_run.tag_top_terms["tag3"][0].term, "rta");
+        assert_eq!(single_run.sites[1].top_terms[0].term, "rta");
+    }
+
+    #[test]
+    fn test11_distinct_words_merge_across_shards() {
+        let single_run = assert_merge_matches_whole(&ProcessOptions {
+            collect_distinct_words: true,
+            ..Default::default()
+        });
+
+        let site_words = single_run.sites[0]
+            .distinct_words
+            .as_ref()
+            .expect("Missing distinct words");
+        assert_eq!(site_words.words(), 15);
+        assert_eq!(site_words.estimate(), 10);
+        assert_eq!(single_run.tag_distinct_words["tag3"].estimate(), 7);
+
+        let mut output = Vec::new();
+        single_run
+            .write_json(&mut output)
+            .expect("Failed to write result");
+        let json: serde_json::Value =
+            serde_json::from_slice(&output).expect("Output is not valid JSON");
+        assert_eq!(
+            json["sites"]["testfile2.jsonl"]["distinct_words"]["distinct"],
+            14
+        );
+        assert!(json["tag_distinct_words"]["tag1"]["diversity"].is_number());
+    }
+
+    #[test]
+    fn test12_heavy_hitters_merge_across_shards() {
+        let single_run = assert_merge_matches_whole(&ProcessOptions {
+            collect_heavy_hitters: true,
+            ..Default::default()
+        });
+
+        let top_words = single_run
+            .totals
+            .top_words
+            .as_ref()
+            .expect("Missing top words");
+        assert_eq!(top_words.words, 33);
+        assert_eq!(top_words.max_error, 0);
+        assert_eq!(top_words.top[0].word, "de");
+        assert_eq!(top_words.top[0].count, 6);
+        let site_words = single_run.sites[1]
+            .top_words
+            .as_ref()
+            .expect("Missing site top words");
+        assert_eq!(site_words.top[0].word, "de");
+        assert_eq!(site_words.top[0].max_count, 3);
+    }
+
+    #[test]
+    fn test13_near_duplicates() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/duplicates/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/duplicates/testfile2.jsonl"),
+        ];
+        let options = ProcessOptions {
+            detect_duplicates: true,
+            ..Default::default()
+        };
+
+        let files_data =
+            process_files_with(files.clone(), &options).expect("Failed to process files");
+        let detected = ResultJson::from_sites("102676".to_string(), files_data);
+        let questions = |result: &ResultJson, site: usize| {
+            let site = &result.sites[site];
+            (site.total_line_count, site.total_word_count)
+        };
+        assert_eq!(questions(&detected, 0), (3, 55));
+        assert_eq!(questions(&detected, 1), (2, 39));
+        assert_eq!(detected.duplicate_clusters.len(), 1);
+        let cluster = &detected.duplicate_clusters[0];
+        assert!(cluster.across_sites);
+        let lines: Vec<(&str, usize)> = cluster
+            .questions
+            .iter()
+            .map(|question| (question.site.as_str(), question.line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("testfile.jsonl", 1),
+                ("testfile.jsonl", 3),
+                ("testfile2.jsonl", 1)
+            ]
+        );
+
+        let options = ProcessOptions {
+            exclude_duplicates: true,
+            ..options
+        };
+        let files_data = process_files_with(files, &options).expect("Failed to process files");
+        let excluded = ResultJson::from_sites("102676".to_string(), files_data);
+        assert_eq!(questions(&excluded, 0), (2, 36));
+        assert_eq!(questions(&excluded, 1), (1, 20));
+        assert_eq!(excluded.sites[0].excluded_duplicate_count, 1);
+        assert_eq!(excluded.sites[1].excluded_duplicate_count, 1);
+        assert_eq!(excluded.tags["rust"], (1, 19));
+        assert!(!excluded.tags.contains_key("vector"));
+        assert_eq!(excluded.duplicate_clusters, detected.duplicate_clusters);
+
+        let mut output = Vec::new();
+        excluded
+            .write_json(&mut output)
+            .expect("Failed to write result");
+        let restored = ResultJson::from_reader(output.as_slice()).expect("Failed to read result");
+        assert_eq!(restored.duplicate_clusters, excluded.duplicate_clusters);
+        assert_eq!(restored.sites[1].duplicates, excluded.sites[1].duplicates);
+    }
+
+    #[test]
+    fn test14_cli_arguments() {
+        let output = run_cli(&["tests/testfiles/2files", "--top", "1", "--threads", "2"]);
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("Output is not valid JSON");
+        assert_eq!(json["totals"]["chatty_tags"], serde_json::json!(["tag1"]));
+        assert_eq!(json["sites"].as_object().map(|sites| sites.len()), Some(2));
+        let output = run_cli(&[
+            "tests/testfiles/2files",
+            "--exclude-duplicates",
+            "--minhash-bands",
+            "8",
+        ]);
+        assert!(output.status.success());
+
+        for (args, message) in [
+            (vec!["--top", "0"], "the ranking size must be at least 1"),
+            (vec!["--top", "ten"], "invalid ranking size"),
+            (
+                vec!["--threads", "0"],
+                "the thread count must be at least 1",
+            ),
+            (vec!["-t", "-1"], "unexpected argument"),
+            (
+                vec!["--minhash-bands", "8"],
+                "required arguments were not provided",
+            ),
+            (
+                vec!["--heavy-hitters", "--heavy-hitter-capacity", "0"],
+                "the heavy hitter capacity must be at least 1",
+            ),
+            (vec!["tests/testfiles/missing"], "does not exist"),
+        ] {
+            let output = run_cli(&args);
+            assert!(!output.status.success(), "{:?} succeeded", args);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+        }
+    }
+
+    #[test]
+    fn test15_state_is_only_written_on_request() {
+        let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test15");
+        std::fs::create_dir_all(&directory).expect("Failed to create directory");
+        let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+
+        for (file, result, distinct_words, state) in [
+            ("testfile", "testfile.json", true, true),
+            ("testfile2", "testfile2.json", true, true),
+            ("testfile2", "report.json", true, false),
+            ("testfile", "plain.json", false, false),
+            ("testfile2", "plain2.json", false, false),
+        ] {
+            let input = format!("tests/testfiles/2files/{}.jsonl", file);
+            let output = path(result);
+            let mut args = vec![input.as_str(), "-o", output.as_str()];
+            if distinct_words {
+                args.push("--distinct-words");
+            }
+            if state {
+                args.push("--state");
+            }
+            assert!(run_cli(&args).status.success());
+        }
+
+        let report: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(path("report.json")).expect("Failed to read result"),
+        )
+        .expect("Output is not valid JSON");
+        let distinct_words = &report["sites"]["testfile2.jsonl"]["distinct_words"];
+        assert_eq!(distinct_words["distinct"], 14);
+        assert!(distinct_words.get("registers").is_none());
+        assert!(report.get("state").is_none());
+        let shard = std::fs::read_to_string(path("testfile.json")).expect("Failed to read result");
+        assert!(shard.contains("\"registers\""));
+
+        let merged = run_cli(&["merge", &path("testfile.json"), &path("testfile2.json")]);
+        assert!(merged.status.success());
+        let json: serde_json::Value =
+            serde_json::from_slice(&merged.stdout).expect("Output is not valid JSON");
+        assert_eq!(
+            json["sites"]["testfile2.jsonl"]["distinct_words"]["distinct"],
+            14
+        );
+        assert!(json["tag_distinct_words"]["tag1"]
+            .get("registers")
+            .is_none());
+
+        let output = run_cli(&["merge", &path("testfile.json"), &path("report.json")]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("was written without --state"), "{}", stderr);
+
+        // Plain question and word counts are exact, so they merge without the state.
+        let merged = run_cli(&["merge", &path("plain.json"), &path("plain2.json")]);
+        assert!(merged.status.success());
+        let whole = run_cli(&["tests/testfiles/2files"]);
+        let json = |stdout: &[u8]| -> serde_json::Value {
+            serde_json::from_slice(stdout).expect("Output is not valid JSON")
+        };
+        assert_eq!(json(&merged.stdout), json(&whole.stdout));
+    }
+
+    #[test]
+    fn test16_raw_counts_are_state() {
+        let args = [
+            "tests/testfiles/2files",
+            "--tag-pairs",
+            "--terms",
+            "--heavy-hitters",
+        ];
+        for state in [false, true] {
+            let mut args = args.to_vec();
+            if state {
+                args.push("--state");
+            }
+            let output = run_cli(&args);
+            assert!(output.status.success());
+            let json: serde_json::Value =
+                serde_json::from_slice(&output.stdout).expect("Output is not valid JSON");
+            let site = &json["sites"]["testfile.jsonl"];
+
+            assert!(json["totals"]["top_tag_pairs"].is_object());
+            assert!(site["top_terms"].is_array());
+            assert!(site["top_words"].is_object());
+            assert_eq!(json.get("tag_pairs").is_some(), state);
+            for field in ["tag_pairs", "terms", "tag_terms", "heavy_hitters"] {
+                assert_eq!(site.get(field).is_some(), state, "{}", field);
+            }
+        }
+    }
+
+    #[test]
+    fn test17_duplicates_of_files_sharing_a_name() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/duplicates/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/1file/testfile.jsonl"),
+        ];
+        let options = ProcessOptions {
+            detect_duplicates: true,
+            exclude_duplicates: true,
+            ..Default::default()
+        };
+
+        let files_data = process_files_with(files, &options).expect("Failed to process files");
+        assert_eq!(files_data.len(), 2);
+        assert_eq!(files_data[0].total_line_count, 2);
+        assert_eq!(files_data[0].excluded_duplicate_count, 1);
+        assert_eq!(files_data[0].duplicates.len(), 1);
+        assert_eq!(files_data[1].total_line_count, 3);
+        assert_eq!(files_data[1].excluded_duplicate_count, 0);
+        assert!(files_data[1].duplicates.is_empty());
+    }
+
+    #[test]
+    fn test18_sites_sharing_a_name_are_rejected() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/duplicates/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/1file/testfile.jsonl"),
+        ];
+        let err = check_site_names(&files).expect_err("Sites sharing a name were accepted");
+        assert!(
+            matches!(&err, Error::DuplicateSite { name, .. } if name == "testfile.jsonl"),
+            "{}",
+            err
+        );
+
+        let output = run_cli(&["tests/testfiles/1file", "tests/testfiles/2files"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("are both the site testfile.jsonl"),
+            "{}",
+            stderr
+        );
+
+        let site = |filename: &str| FileData {
+            filename: filename.to_string(),
+            ..Default::default()
+        };
+        let result = ResultJson {
+            sites: vec![site("testfile.jsonl"), site("testfile.jsonl")],
+            ..Default::default()
+        };
+        for sort_keys in [false, true] {
+            let options = OutputOptions { sort_keys };
+            let mut output = Vec::new();
+            assert!(result.write_json_with(&mut output, &options).is_err());
+        }
+
+        let document = r#"{"padron": "102676",
+            "sites": {"testfile.jsonl": {"questions": 1, "words": 2, "tags": {}, "chatty_tags": []},
+                      "testfile.jsonl": {"questions": 3, "words": 4, "tags": {}, "chatty_tags": []}},
+            "tags": {}, "totals": {"chatty_sites": [], "chatty_tags": []}}"#;
+        assert!(ResultJson::from_reader(document.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test19_directories_are_read_in_order() {
+        let output = run_cli(&["tests/testfiles/duplicates", "--duplicates"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let first = stdout.find("\"testfile.jsonl\": {").expect("Missing site");
+        let second = stdout.find("\"testfile2.jsonl\": {").expect("Missing site");
+        assert!(first < second);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&stdout).expect("Output is not valid JSON");
+        let kept = &json["duplicate_clusters"][0]["questions"][0];
+        assert_eq!(kept["site"], "testfile.jsonl");
+        assert_eq!(kept["line"], 1);
+    }
 }
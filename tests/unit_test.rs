@@ -1,12 +1,60 @@
+#![allow(clippy::bool_assert_comparison, clippy::redundant_field_names)]
+
 #[cfg(test)]
 mod tests {
 
     use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Arc;
     use tp1::{
-        aggregate_tag_counts, generate_top_tags, process_files, top_10_filenames_highest_ratio,
-        top_10_tags_highest_ratio, FileData,
+        aggregate_tag_counts, chunk_ranges, find_duplicates, generate_top_n_tags,
+        generate_top_tags, normalize_text, par_top_k, process_files, process_files_with,
+        read_batch, top_10_filenames_highest_ratio, top_10_tags_highest_ratio,
+        top_n_filenames_highest_ratio, top_n_tag_pairs, top_n_tags_by, top_n_tags_highest_ratio,
+        top_n_terms_tf_idf, CodeBlocks, DistinctSketch, Error, ErrorPolicy, FileData, HeavyHitters,
+        LengthSketch, MarkupTokenizer, MinHashOptions, Normalization, ProcessOptions, QuestionRef,
+        QuestionSignature, RankingMetric, RankingOptions, ResultJson, Schema, SkippedLines,
+        TagFormat, TagPairs, TermCounts, TextCounts, TextRole, Tokenizer, UnicodeWordTokenizer,
+        WhitespaceTokenizer,
     };
 
+    /// Process files in a thread pool with the given number of threads.
+    fn process_with_threads(
+        files: &[PathBuf],
+        options: &ProcessOptions,
+        threads: usize,
+    ) -> Vec<FileData> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("Failed to build thread pool")
+            .install(|| process_files_with(files.to_vec(), options))
+            .expect("Failed to process files")
+    }
+
+    /// Write a file of 200 questions in a temporary directory, where lines 57 and 150 are
+    /// not valid JSON and every 40th line repeats the same question.
+    fn write_numbered_questions(directory: &str) -> PathBuf {
+        let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(directory);
+        std::fs::create_dir_all(&directory).expect("Failed to create directory");
+        let path = directory.join("numbered.jsonl");
+        let lines: Vec<String> = (1..=200)
+            .map(|line| match line {
+                57 | 150 => String::from("not json"),
+                _ if line % 40 == 0 => {
+                    String::from(r#"{"texts": ["the same repeated question"], "tags": ["dup"]}"#)
+                }
+                _ => format!(
+                    r#"{{"texts": ["question number {} with words"], "tags": ["tag{}"]}}"#,
+                    line,
+                    line % 3
+                ),
+            })
+            .collect();
+        std::fs::write(&path, lines.join("\n")).expect("Failed to write file");
+        path
+    }
+
     // Function to compare two FileData structs
     pub fn compare_file_data(file_data1: &FileData, file_data2: &FileData) -> bool {
         file_data1.filename == file_data2.filename
@@ -29,6 +77,7 @@ mod tests {
             total_line_count: 3,
             tag_counts: tag_counts,
             top_tags: Vec::new(),
+            ..Default::default()
         };
 
         let directory: &str = "tests/testfiles/1file";
@@ -38,7 +87,7 @@ mod tests {
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("Failed to collect file paths");
 
-        let processed_file_data = &process_files(files)[0];
+        let processed_file_data = &process_files(files).expect("Failed to process files")[0];
 
         assert_eq!(true, compare_file_data(processed_file_data, &file_data));
     }
@@ -58,6 +107,7 @@ mod tests {
             total_line_count: 3,
             tag_counts,
             top_tags,
+            ..Default::default()
         };
 
         let directory: &str = "tests/testfiles/1file";
@@ -67,7 +117,7 @@ mod tests {
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("Failed to collect file paths");
 
-        let mut processed_file_data = process_files(files);
+        let mut processed_file_data = process_files(files).expect("Failed to process files");
         generate_top_tags(&mut processed_file_data);
         let final_file_data = &processed_file_data[0];
 
@@ -88,7 +138,7 @@ mod tests {
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("Failed to collect file paths");
 
-        let mut processed_file_data = process_files(files);
+        let mut processed_file_data = process_files(files).expect("Failed to process files");
         generate_top_tags(&mut processed_file_data);
         let aggregated_tag_counts = aggregate_tag_counts(&processed_file_data);
 
@@ -107,7 +157,7 @@ mod tests {
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("Failed to collect file paths");
 
-        let mut processed_file_data = process_files(files);
+        let mut processed_file_data = process_files(files).expect("Failed to process files");
         generate_top_tags(&mut processed_file_data);
         let aggregated_tag_counts = aggregate_tag_counts(&processed_file_data);
         let top_10_tags = top_10_tags_highest_ratio(&aggregated_tag_counts);
@@ -127,10 +177,1054 @@ mod tests {
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("Failed to collect file paths");
 
-        let mut processed_file_data = process_files(files);
+        let mut processed_file_data = process_files(files).expect("Failed to process files");
         generate_top_tags(&mut processed_file_data);
         let top_10_filenames = top_10_filenames_highest_ratio(&processed_file_data);
 
         assert_eq!(true, top_10_filenames == filenames);
     }
+
+    #[test]
+    fn test06_process_compressed_files() {
+        let directory: &str = "tests/testfiles/1file";
+        let files = std::fs::read_dir(directory)
+            .expect("Failed to open directory")
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()
+            .expect("Failed to collect file paths");
+        let plain_file_data = &process_files(files).expect("Failed to process files")[0];
+
+        let directory: &str = "tests/testfiles/compressed";
+        let files = std::fs::read_dir(directory)
+            .expect("Failed to open directory")
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()
+            .expect("Failed to collect file paths");
+        let compressed_files_data = process_files(files).expect("Failed to process files");
+
+        assert_eq!(compressed_files_data.len(), 3);
+        for compressed_file_data in &compressed_files_data {
+            assert!(compare_file_data(compressed_file_data, plain_file_data));
+        }
+    }
+
+    #[test]
+    fn test07_chunk_ranges_end_at_line_breaks() {
+        let bytes = b"a\nbb\nccc\ndddd";
+
+        let ranges = chunk_ranges(bytes, 2);
+
+        assert_eq!(ranges, vec![0..2, 2..5, 5..9, 9..13]);
+        assert_eq!(chunk_ranges(bytes, 100), vec![0..13]);
+        assert!(chunk_ranges(b"", 2).is_empty());
+    }
+
+    #[test]
+    fn test08_malformed_line_returns_json_error() {
+        let files = vec![PathBuf::from("tests/testfiles/malformed/testfile.jsonl")];
+
+        match process_files(files) {
+            Err(Error::Json { file, line, .. }) => {
+                assert_eq!(file, "testfile.jsonl");
+                assert_eq!(line, 2);
+            }
+            other => panic!("Expected a JSON error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test09_invalid_question_returns_schema_error() {
+        let files = vec![PathBuf::from("tests/testfiles/schema/testfile.jsonl.gz")];
+
+        match process_files(files) {
+            Err(Error::Schema { file, line, .. }) => {
+                assert_eq!(file, "testfile.jsonl");
+                assert!((2..=4).contains(&line));
+            }
+            other => panic!("Expected a schema error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test10_skip_invalid_lines() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/malformed/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/schema/testfile.jsonl.gz"),
+        ];
+        let options = ProcessOptions {
+            on_error: ErrorPolicy::Skip,
+            require_fields: true,
+            ..Default::default()
+        };
+
+        let processed_file_data =
+            process_files_with(files, &options).expect("Failed to process files");
+
+        let malformed = &processed_file_data[0];
+        assert_eq!(malformed.total_line_count, 2);
+        assert_eq!(malformed.total_word_count, 11);
+        assert_eq!(
+            malformed.skipped,
+            SkippedLines {
+                malformed: 1,
+                non_object: 0,
+                missing_field: 0,
+                sample_lines: vec![2],
+            }
+        );
+
+        // A single string is a valid text field, so only lines 2 and 3 are skipped.
+        let schema = &processed_file_data[1];
+        assert_eq!(schema.total_line_count, 2);
+        assert_eq!(
+            schema.skipped,
+            SkippedLines {
+                malformed: 0,
+                non_object: 1,
+                missing_field: 1,
+                sample_lines: vec![2, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn test11_top_n_rankings() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+        ];
+
+        let mut processed_file_data = process_files(files).expect("Failed to process files");
+        generate_top_n_tags(&mut processed_file_data, 1, 0);
+        let aggregated_tag_counts = aggregate_tag_counts(&processed_file_data);
+
+        for file_data in &processed_file_data {
+            assert_eq!(file_data.top_tags.len(), 1);
+        }
+        assert_eq!(
+            top_n_tags_highest_ratio(&aggregated_tag_counts, 1, 0).len(),
+            1
+        );
+        assert_eq!(
+            top_n_tags_highest_ratio(&aggregated_tag_counts, 100, 0).len(),
+            aggregated_tag_counts.len()
+        );
+        assert_eq!(
+            top_n_filenames_highest_ratio(&processed_file_data, 1, 0),
+            top_10_filenames_highest_ratio(&processed_file_data)[..1]
+        );
+    }
+
+    #[test]
+    fn test12_par_top_k_matches_full_sort() {
+        use rayon::prelude::*;
+
+        let scores: Vec<(usize, f64)> = (0..10_000)
+            .map(|item| (item, ((item * 7_919) % 10_007) as f64))
+            .collect();
+        let mut sorted = scores.clone();
+        sorted.sort_by(|(_, score1), (_, score2)| score2.total_cmp(score1));
+
+        for k in [0, 1, 10, 10_000, 20_000] {
+            let top_k = par_top_k(scores.par_iter().copied(), k);
+            let expected: Vec<usize> = sorted.iter().take(k).map(|&(item, _)| item).collect();
+            assert_eq!(top_k, expected);
+        }
+    }
+
+    #[test]
+    fn test13_ties_are_ranked_by_name() {
+        let tag_counts: HashMap<String, (usize, usize)> = ["delta", "alpha", "charlie", "bravo"]
+            .iter()
+            .map(|tag| (tag.to_string(), (2, 10)))
+            .collect();
+
+        let top_tags = top_n_tags_highest_ratio(&tag_counts, 3, 0);
+
+        assert_eq!(top_tags, vec!["alpha", "bravo", "charlie"]);
+    }
+
+    #[test]
+    fn test14_min_questions_excludes_low_support() {
+        let tag_counts: HashMap<String, (usize, usize)> = [
+            ("rare".to_string(), (1, 500)),
+            ("common".to_string(), (1_000, 20_000)),
+            ("popular".to_string(), (5_000, 50_000)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(top_n_tags_highest_ratio(&tag_counts, 1, 0), vec!["rare"]);
+        assert_eq!(
+            top_n_tags_highest_ratio(&tag_counts, 10, 1_000),
+            vec!["common", "popular"]
+        );
+
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+        ];
+        let mut processed_file_data = process_files(files).expect("Failed to process files");
+        generate_top_n_tags(&mut processed_file_data, 10, 2);
+
+        assert_eq!(processed_file_data[0].top_tags, vec!["tag2", "tag1"]);
+        assert!(top_n_filenames_highest_ratio(&processed_file_data, 10, 4).is_empty());
+        assert_eq!(processed_file_data[0].tag_counts.len(), 2);
+    }
+
+    #[test]
+    fn test15_length_sketch_quantiles() {
+        let mut sketch = LengthSketch::default();
+        let mut other = LengthSketch::default();
+        for length in 0..=1000 {
+            if length % 2 == 0 {
+                sketch.insert(length);
+            } else {
+                other.insert(length);
+            }
+        }
+        sketch.merge(&other);
+
+        assert_eq!(sketch.count(), 1001);
+        assert_eq!(sketch.min(), Some(0));
+        assert_eq!(sketch.max(), Some(1000));
+        for (quantile, expected) in [(0.0, 0.0), (0.5, 500.0), (0.9, 900.0), (1.0, 1000.0)] {
+            let estimate = sketch.quantile(quantile).expect("Empty sketch");
+            assert!((estimate - expected).abs() <= expected * 0.01);
+        }
+        assert_eq!(LengthSketch::default().quantile(0.5), None);
+    }
+
+    #[test]
+    fn test16_ranking_metrics() {
+        let tag_counts: HashMap<String, (usize, usize)> = [
+            ("rare".to_string(), (1, 100)),
+            ("common".to_string(), (100, 8_000)),
+            ("short".to_string(), (100, 1_000)),
+        ]
+        .into_iter()
+        .collect();
+        let no_lengths = HashMap::new();
+        let smoothed = RankingMetric::SmoothedMean {
+            prior_questions: 10,
+        };
+
+        assert_eq!(
+            top_n_tags_by(&tag_counts, &no_lengths, 3, 0, RankingMetric::Mean),
+            vec!["rare", "common", "short"]
+        );
+        assert_eq!(
+            top_n_tags_by(&tag_counts, &no_lengths, 3, 0, smoothed),
+            vec!["common", "rare", "short"]
+        );
+        assert_eq!(
+            top_n_tags_by(&tag_counts, &no_lengths, 3, 0, RankingMetric::TotalWords),
+            vec!["common", "short", "rare"]
+        );
+        assert_eq!(
+            top_n_tags_by(&tag_counts, &no_lengths, 3, 0, RankingMetric::Questions),
+            vec!["common", "short", "rare"]
+        );
+
+        let mut skewed = LengthSketch::default();
+        for length in [1, 1, 1, 1, 100] {
+            skewed.insert(length);
+        }
+        let mut steady = LengthSketch::default();
+        for length in [10, 10, 10] {
+            steady.insert(length);
+        }
+        let tag_counts: HashMap<String, (usize, usize)> = [
+            ("skewed".to_string(), (5, 104)),
+            ("steady".to_string(), (3, 30)),
+        ]
+        .into_iter()
+        .collect();
+        let tag_lengths: HashMap<String, LengthSketch> = [
+            ("skewed".to_string(), skewed),
+            ("steady".to_string(), steady),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            top_n_tags_by(&tag_counts, &tag_lengths, 2, 0, RankingMetric::Mean),
+            vec!["skewed", "steady"]
+        );
+        assert_eq!(
+            top_n_tags_by(&tag_counts, &tag_lengths, 2, 0, RankingMetric::Median),
+            vec!["steady", "skewed"]
+        );
+    }
+
+    #[test]
+    fn test17_collect_lengths() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+        ];
+        let options = ProcessOptions {
+            collect_lengths: true,
+            ..Default::default()
+        };
+
+        let files_data = process_files_with(files, &options).expect("Failed to process files");
+        let ranking = RankingOptions {
+            metric: RankingMetric::P90,
+            ..Default::default()
+        };
+        let result = ResultJson::from_sites_with("102676".to_string(), files_data, &ranking);
+
+        let lengths = result.sites[1].lengths.as_ref().expect("Missing lengths");
+        assert_eq!(lengths.count(), 3);
+        assert_eq!((lengths.min(), lengths.max()), (Some(4), Some(9)));
+        assert_eq!(result.sites[1].tag_lengths["tag3"].count(), 2);
+        assert_eq!(
+            result.totals.chatty_sites,
+            vec!["testfile.jsonl", "testfile2.jsonl"]
+        );
+        assert_eq!(result.totals.chatty_tags, vec!["tag1", "tag2", "tag3"]);
+    }
+
+    #[test]
+    fn test18_words_by_text_role() {
+        let files = vec![PathBuf::from("tests/testfiles/2files/testfile2.jsonl")];
+        let options = ProcessOptions {
+            collect_texts: true,
+            ..Default::default()
+        };
+
+        let file_data =
+            &process_files_with(files.clone(), &options).expect("Failed to process files")[0];
+
+        assert_eq!(
+            file_data.texts,
+            TextCounts {
+                title_words: 3,
+                body_words: 15,
+                other_words: 0,
+                entries: 6,
+            }
+        );
+        assert_eq!(file_data.texts.words(), file_data.total_word_count);
+        assert_eq!(file_data.tag_texts["tag3"].body_words, 7);
+
+        let options = ProcessOptions {
+            collect_texts: true,
+            text_roles: vec![TextRole::Body],
+            ..Default::default()
+        };
+        let file_data = &process_files_with(files, &options).expect("Failed to process files")[0];
+
+        assert_eq!(file_data.texts.title_words, 0);
+        assert_eq!(file_data.texts.body_words, 3);
+        assert_eq!(file_data.texts.other_words, 15);
+    }
+
+    #[test]
+    fn test19_custom_schema() {
+        let files = vec![PathBuf::from(
+            "tests/testfiles/custom_schema/testfile.jsonl",
+        )];
+        let options = ProcessOptions {
+            schema: Schema {
+                text_pointers: vec!["/title".to_string(), "/body/text".to_string()],
+                tag_pointer: "/labels".to_string(),
+                tag_format: TagFormat::Enclosed {
+                    open: '<',
+                    close: '>',
+                },
+            },
+            collect_texts: true,
+            ..Default::default()
+        };
+
+        let file_data =
+            &process_files_with(files.clone(), &options).expect("Failed to process files")[0];
+
+        assert_eq!(file_data.total_line_count, 2);
+        assert_eq!(file_data.total_word_count, 15);
+        assert_eq!(file_data.tag_counts["rust"], (1, 8));
+        assert_eq!(file_data.tag_counts["borrow-checker"], (1, 8));
+        assert_eq!(file_data.tag_counts["python"], (1, 7));
+        assert_eq!(file_data.texts.title_words, 5);
+        assert_eq!(file_data.texts.other_words, 2);
+
+        let strict = ProcessOptions {
+            require_fields: true,
+            ..Default::default()
+        };
+        match process_files_with(files, &strict) {
+            Err(Error::Schema { line, .. }) => assert_eq!(line, 1),
+            other => panic!("Expected a schema error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test20_tokenizers() {
+        let text = "<p>Use <code>foo,bar</code> &amp; **bold** text</p>";
+        let whitespace: Vec<&str> = WhitespaceTokenizer.tokens(text).collect();
+        let unicode: Vec<&str> = UnicodeWordTokenizer.tokens(text).collect();
+        let markup: Vec<&str> = MarkupTokenizer.tokens(text).collect();
+
+        assert_eq!(
+            whitespace,
+            vec![
+                "<p>Use",
+                "<code>foo,bar</code>",
+                "&amp;",
+                "**bold**",
+                "text</p>"
+            ]
+        );
+        assert_eq!(
+            unicode,
+            vec!["p", "Use", "code", "foo", "bar", "code", "amp", "bold", "text", "p"]
+        );
+        assert_eq!(markup, vec!["Use", "foo", "bar", "bold", "text"]);
+        assert_eq!(MarkupTokenizer.count_words("if a < b then"), 4);
+        assert_eq!(UnicodeWordTokenizer.count_words("我爱编程"), 4);
+
+        let files = vec![PathBuf::from("tests/testfiles/2files/testfile.jsonl")];
+        let whitespace_data = &process_files(files.clone()).expect("Failed to process files")[0];
+        let options = ProcessOptions {
+            tokenizer: Arc::new(UnicodeWordTokenizer),
+            ..Default::default()
+        };
+        let unicode_data =
+            &process_files_with(files, &options).expect("Failed to process files")[0];
+
+        assert_eq!(
+            unicode_data.total_line_count,
+            whitespace_data.total_line_count
+        );
+        assert_eq!(
+            unicode_data.tag_counts.len(),
+            whitespace_data.tag_counts.len()
+        );
+    }
+
+    #[test]
+    fn test21_normalization() {
+        let normalization = Normalization {
+            strip_html: true,
+            code_blocks: CodeBlocks::Separate,
+            collapse_urls: true,
+        };
+        let text = "<p>Read (see https://example.com/a?b=c).</p>\n\n    x = 1\n\nThen &amp; now";
+        let normalized = normalize_text(text, &normalization);
+
+        assert_eq!(
+            normalized.prose.split_whitespace().collect::<Vec<_>>(),
+            vec!["Read", "(see", "URL).", "Then", "&", "now"]
+        );
+        assert_eq!(normalized.code, "    x = 1\n");
+        assert_eq!(normalize_text(text, &Normalization::default()).prose, text);
+
+        let files = vec![PathBuf::from("tests/testfiles/markup/testfile.jsonl")];
+        let kept = &process_files(files.clone()).expect("Failed to process files")[0];
+        let options = ProcessOptions {
+            normalization,
+            ..Default::default()
+        };
+        let separated =
+            &process_files_with(files.clone(), &options).expect("Failed to process files")[0];
+        let options = ProcessOptions {
+            normalization: Normalization {
+                code_blocks: CodeBlocks::Exclude,
+                ..normalization
+            },
+            ..Default::default()
+        };
+        let excluded = &process_files_with(files, &options).expect("Failed to process files")[0];
+
+        assert_eq!(kept.total_word_count, 30);
+        assert_eq!(kept.code_word_count, 0);
+        assert_eq!(separated.total_word_count, 21);
+        assert_eq!(separated.code_word_count, 7);
+        assert_eq!(separated.tag_counts["python"], (1, 8));
+        assert_eq!(excluded.total_word_count, 21);
+        assert_eq!(excluded.code_word_count, 0);
+    }
+
+    #[test]
+    fn test22_tag_pairs() {
+        let mut pairs = TagPairs::default();
+        pairs.record(&["c", "a", "b", "a"], 10);
+        pairs.record(&["a", "b", "d", "e"], 2);
+        pairs.record(&["a"], 10);
+
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs.count("a", "b"), 2);
+        assert_eq!(pairs.count("b", "a"), 2);
+        assert_eq!(pairs.count("a", "c"), 1);
+        assert_eq!(pairs.count("b", "c"), 1);
+        assert_eq!(pairs.count("a", "d"), 0);
+
+        let mut tag_counts = HashMap::new();
+        tag_counts.insert("a".to_string(), (3, 0));
+        tag_counts.insert("b".to_string(), (2, 0));
+        tag_counts.insert("c".to_string(), (1, 0));
+        let top = top_n_tag_pairs(&pairs, &tag_counts, 4, 2, 0);
+
+        let by_count: Vec<(&str, &str)> = top
+            .by_count
+            .iter()
+            .map(|pair| (pair.tags.0.as_str(), pair.tags.1.as_str()))
+            .collect();
+        let by_pmi: Vec<(&str, &str)> = top
+            .by_pmi
+            .iter()
+            .map(|pair| (pair.tags.0.as_str(), pair.tags.1.as_str()))
+            .collect();
+        assert_eq!(by_count, vec![("a", "b"), ("a", "c")]);
+        assert_eq!(by_pmi, vec![("b", "c"), ("a", "b")]);
+        assert!((top.by_pmi[0].lift - 2.0).abs() < 1e-9);
+        assert!((top.by_pmi[0].pmi - 2.0f64.ln()).abs() < 1e-9);
+
+        let supported = top_n_tag_pairs(&pairs, &tag_counts, 4, 2, 2);
+        assert_eq!(supported.by_pmi.len(), 1);
+        assert_eq!(supported.by_pmi[0].questions, 2);
+    }
+
+    #[test]
+    fn test23_distinctive_terms() {
+        let mut terms = TermCounts::default();
+        for term in ["a", "a", "a", "b", "b", "e", "d", "c"] {
+            terms.insert(term);
+        }
+        assert_eq!(terms.len(), 5);
+        terms.truncate(3);
+        assert_eq!(terms.len(), 3);
+        assert_eq!(
+            (terms.count("a"), terms.count("b"), terms.count("c")),
+            (3, 2, 1)
+        );
+
+        let mut first = TermCounts::default();
+        let mut second = TermCounts::default();
+        for term in ["rust", "rust", "memory"] {
+            first.insert(term);
+        }
+        for term in ["memory", "memory", "memory"] {
+            second.insert(term);
+        }
+        let top = top_n_terms_tf_idf(&[&first, &second], 2);
+
+        assert_eq!(top[0][0].term, "rust");
+        assert_eq!(top[0][0].count, 2);
+        assert!((top[0][0].tf_idf - 2.0 / 3.0 * (1.5f64.ln() + 1.0)).abs() < 1e-9);
+        assert_eq!(top[0][1].term, "memory");
+        assert_eq!(top[1].len(), 1);
+        assert!((top[1][0].tf_idf - 1.0).abs() < 1e-9);
+
+        let files = vec![PathBuf::from("tests/testfiles/2files/testfile.jsonl")];
+        let options = ProcessOptions {
+            collect_terms: true,
+            ..Default::default()
+        };
+        let file_data = &process_files_with(files, &options).expect("Failed to process files")[0];
+
+        assert_eq!(file_data.terms.count("respuesta"), 3);
+        assert_eq!(file_data.terms.count("de"), 0);
+        assert_eq!(file_data.tag_terms["tag1"].count("tres"), 1);
+    }
+
+    #[test]
+    fn test24_distinct_sketch() {
+        let mut whole = DistinctSketch::new(10);
+        let mut halves = [DistinctSketch::new(10), DistinctSketch::new(10)];
+        let mut coarse = DistinctSketch::new(8);
+        for word in 0..50_000 {
+            let word = format!("word{}", word);
+            for _ in 0..2 {
+                whole.insert(&word);
+            }
+            halves[word.len() % 2].insert(&word);
+            halves[word.len() % 2].insert(&word);
+            coarse.insert(&word);
+        }
+
+        let error = (whole.estimate() as f64 - 50_000.0).abs() / 50_000.0;
+        assert!(error < 3.0 * 0.0325, "estimate {}", whole.estimate());
+        assert_eq!(whole.words(), 100_000);
+        let diversity = whole.diversity().expect("Missing diversity");
+        assert!((diversity - 0.5).abs() < 0.5 * 3.0 * 0.0325);
+
+        let [mut merged, other] = halves;
+        merged.merge(&other);
+        assert_eq!(merged, whole);
+
+        let mut mixed = whole.clone();
+        mixed.merge(&coarse);
+        let mut expected = coarse.clone();
+        expected.merge(&whole);
+        assert_eq!(mixed, expected);
+        let error = (mixed.estimate() as f64 - 50_000.0).abs() / 50_000.0;
+        assert!(error < 3.0 * 0.065, "estimate {}", mixed.estimate());
+
+        let json = serde_json::to_string(&whole).expect("Failed to write sketch");
+        let read: DistinctSketch = serde_json::from_str(&json).expect("Failed to read sketch");
+        assert_eq!(read, whole);
+        for precision in [3, 17, 64, 200] {
+            let json = format!(
+                r#"{{"words": 1, "precision": {}, "registers": "01"}}"#,
+                precision
+            );
+            assert!(serde_json::from_str::<DistinctSketch>(&json).is_err());
+        }
+
+        let mut few = DistinctSketch::new(10);
+        for word in ["a", "b", "a", "c"] {
+            few.insert(word);
+        }
+        assert_eq!(few.estimate(), 3);
+        let json: serde_json::Value = serde_json::to_value(&few).expect("Failed to write sketch");
+        assert_eq!(json["registers"].as_array().map(Vec::len), Some(3));
+        let read: DistinctSketch = serde_json::from_value(json).expect("Failed to read sketch");
+        assert_eq!(read, few);
+        let mut sparse_first = few.clone();
+        sparse_first.merge(&whole);
+        let mut dense_first = whole.clone();
+        dense_first.merge(&few);
+        assert_eq!(sparse_first, dense_first);
+
+        let mut report = few.clone();
+        report.drop_registers();
+        let json = serde_json::to_value(&report).expect("Failed to write sketch");
+        assert!(json.get("registers").is_none());
+        let read: DistinctSketch = serde_json::from_value(json).expect("Failed to read sketch");
+        assert_eq!((read.words(), read.estimate()), (4, 3));
+        assert_eq!(DistinctSketch::default().diversity(), None);
+    }
+
+    #[test]
+    fn test25_heavy_hitters() {
+        let mut true_counts: HashMap<String, u64> = HashMap::new();
+        let mut whole = HeavyHitters::new(10);
+        let mut halves = [HeavyHitters::new(10), HeavyHitters::new(10)];
+        let mut state: u64 = 7;
+        for position in 0..20_000 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let rank = (state >> 33) % 1_000;
+            let word = if rank < 300 {
+                format!("common{}", rank % 3)
+            } else {
+                format!("rare{}", rank)
+            };
+            *true_counts.entry(word.clone()).or_insert(0) += 1;
+            whole.insert(&word);
+            halves[position % 2].insert(&word);
+        }
+        whole.prune();
+        let [mut merged, mut other] = halves;
+        merged.prune();
+        other.prune();
+        merged.merge(&other);
+        merged.prune();
+        assert!(whole.error() > 0);
+
+        for summary in [&whole, &merged] {
+            assert_eq!(summary.words(), 20_000);
+            assert!(summary.error() <= 20_000 / 11);
+            let top = summary.top_k(3);
+            assert_eq!(top.max_error, summary.error());
+            let mut words: Vec<&str> = top.top.iter().map(|word| word.word.as_str()).collect();
+            words.sort_unstable();
+            assert_eq!(words, vec!["common0", "common1", "common2"]);
+            for word in &top.top {
+                let true_count = true_counts[&word.word];
+                assert!(word.count <= true_count && true_count <= word.max_count);
+            }
+        }
+    }
+
+    #[test]
+    fn test26_minhash_duplicates() {
+        let options = MinHashOptions::default();
+        let words = |range: std::ops::Range<usize>, prefix: &str| -> Vec<String> {
+            range.map(|index| format!("{}{}", prefix, index)).collect()
+        };
+        let original = words(0..100, "w");
+        let mut edited = words(0..90, "w");
+        edited.extend(words(90..100, "x"));
+        let unrelated = words(0..100, "y");
+
+        let signature = |line, words: &[String]| {
+            QuestionSignature::new(line, words, &options).expect("Missing signature")
+        };
+        let original_signature = signature(1, &original);
+        assert_eq!(original_signature.hashes.len(), options.hashes());
+        assert!(QuestionSignature::new(1, &[], &options).is_none());
+
+        // The edited text shares 88 of the 108 distinct shingles of both texts.
+        let similarity = original_signature.similarity(&signature(2, &edited));
+        assert!((similarity - 88.0 / 108.0).abs() < 0.15, "{}", similarity);
+        assert!(original_signature.similarity(&signature(3, &unrelated)) < 0.1);
+
+        let site1 = FileData {
+            filename: String::from("site1.jsonl"),
+            signatures: vec![original_signature.clone(), signature(2, &unrelated)],
+            ..Default::default()
+        };
+        let site2 = FileData {
+            filename: String::from("site2.jsonl"),
+            signatures: vec![signature(4, &original)],
+            ..Default::default()
+        };
+        let duplicates = find_duplicates(&[site1, site2], &options, 0.8);
+
+        assert!(duplicates[0].is_empty());
+        assert_eq!(duplicates[1].len(), 1);
+        assert_eq!(duplicates[1][0].line, 4);
+        assert_eq!(
+            duplicates[1][0].of,
+            QuestionRef {
+                site: String::from("site1.jsonl"),
+                line: 1,
+            }
+        );
+        assert_eq!(duplicates[1][0].similarity, 1.0);
+    }
+
+    #[test]
+    fn test27_read_batch_keeps_lines_whole() {
+        let input = "first\nsecond line here\nthird";
+        let mut reader = std::io::Cursor::new(input);
+
+        let batches: Vec<Vec<u8>> = std::iter::from_fn(|| {
+            let batch = read_batch(&mut reader, 10).expect("Failed to read batch");
+            (!batch.is_empty()).then_some(batch)
+        })
+        .collect();
+        assert_eq!(batches, vec![&b"first\nsecond line here\n"[..], b"third"]);
+
+        let mut reader = std::io::Cursor::new(input);
+        let batches: Vec<Vec<u8>> = std::iter::from_fn(|| {
+            let batch = read_batch(&mut reader, 0).expect("Failed to read batch");
+            (!batch.is_empty()).then_some(batch)
+        })
+        .collect();
+        assert_eq!(
+            batches,
+            vec![&b"first\n"[..], b"second line here\n", b"third"]
+        );
+        assert_eq!(batches.concat(), input.as_bytes());
+    }
+
+    #[test]
+    fn test28_missing_fields_count_as_empty() {
+        let files = vec![PathBuf::from("tests/testfiles/schema/testfile.jsonl.gz")];
+        let options = ProcessOptions {
+            on_error: ErrorPolicy::Skip,
+            ..Default::default()
+        };
+
+        let processed_file_data =
+            process_files_with(files, &options).expect("Failed to process files");
+
+        // Only the line that is not an object is skipped; the one without tags is counted.
+        let schema = &processed_file_data[0];
+        assert_eq!(schema.total_line_count, 3);
+        assert_eq!(
+            schema.skipped,
+            SkippedLines {
+                malformed: 0,
+                non_object: 1,
+                missing_field: 0,
+                sample_lines: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn test29_skip_lines_that_are_not_utf8() {
+        let files = vec![PathBuf::from("tests/testfiles/utf8/testfile.jsonl")];
+
+        match process_files(files.clone()) {
+            Err(Error::Json { file, line, .. }) => {
+                assert_eq!(file, "testfile.jsonl");
+                assert_eq!(line, 2);
+            }
+            other => panic!("Expected a JSON error, got {:?}", other),
+        }
+
+        let options = ProcessOptions {
+            on_error: ErrorPolicy::Skip,
+            ..Default::default()
+        };
+        let file_data = &process_files_with(files, &options).expect("Failed to process files")[0];
+        assert_eq!(file_data.total_line_count, 2);
+        assert_eq!(file_data.total_word_count, 8);
+        assert_eq!(
+            file_data.skipped,
+            SkippedLines {
+                malformed: 1,
+                non_object: 0,
+                missing_field: 0,
+                sample_lines: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn test30_tags_between_equal_delimiters() {
+        let files = vec![PathBuf::from(
+            "tests/testfiles/same_delimiters/testfile.jsonl",
+        )];
+        let options = ProcessOptions {
+            schema: Schema {
+                tag_format: TagFormat::Enclosed {
+                    open: '|',
+                    close: '|',
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let file_data = &process_files_with(files, &options).expect("Failed to process files")[0];
+
+        assert_eq!(file_data.tag_counts.len(), 2);
+        assert_eq!(file_data.tag_counts["rust"], (2, 4));
+        assert_eq!(file_data.tag_counts["serde"], (1, 2));
+    }
+
+    #[test]
+    fn test31_term_caps_do_not_depend_on_threads() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+            PathBuf::from("tests/testfiles/duplicates/testfile.jsonl"),
+        ];
+        let options = ProcessOptions {
+            collect_terms: true,
+            max_terms: 3,
+            ..Default::default()
+        };
+
+        let single = process_with_threads(&files, &options, 1);
+        let parallel = process_with_threads(&files, &options, 4);
+
+        for (site1, site2) in single.iter().zip(&parallel) {
+            assert_eq!(site1.terms.len(), 3);
+            assert_eq!(site1.terms, site2.terms);
+            assert_eq!(site1.tag_terms, site2.tag_terms);
+        }
+    }
+
+    #[test]
+    fn test32_heavy_hitters_do_not_depend_on_threads() {
+        let files = vec![
+            PathBuf::from("tests/testfiles/2files/testfile.jsonl"),
+            PathBuf::from("tests/testfiles/2files/testfile2.jsonl"),
+            PathBuf::from("tests/testfiles/duplicates/testfile.jsonl"),
+        ];
+        let options = ProcessOptions {
+            collect_heavy_hitters: true,
+            heavy_hitter_capacity: 2,
+            ..Default::default()
+        };
+
+        let single = process_with_threads(&files, &options, 1);
+        let parallel = process_with_threads(&files, &options, 4);
+
+        for (site1, site2) in single.iter().zip(&parallel) {
+            assert_eq!(site1.heavy_hitters, site2.heavy_hitters);
+        }
+        let single = ResultJson::from_sites("102676".to_string(), single);
+        let parallel = ResultJson::from_sites("102676".to_string(), parallel);
+        let top_words = single.totals.top_words.as_ref().expect("Missing top words");
+        assert!(top_words.max_error > 0);
+        assert_eq!(single.totals.top_words, parallel.totals.top_words);
+    }
+
+    #[test]
+    fn test33_duplicates_are_similar_to_the_question_kept() {
+        let options = MinHashOptions::default();
+        // Each question shares 56 of its 64 hashes with the previous one, but the first
+        // and the last share only 48.
+        let first: Vec<u32> = (0..64).collect();
+        let mut second = first.clone();
+        second[56..].copy_from_slice(&[1000, 1001, 1002, 1003, 1004, 1005, 1006, 1007]);
+        let mut third = second.clone();
+        third[..8].copy_from_slice(&[2000, 2001, 2002, 2003, 2004, 2005, 2006, 2007]);
+        let site = FileData {
+            filename: String::from("site.jsonl"),
+            signatures: [first, second, third]
+                .into_iter()
+                .enumerate()
+                .map(|(line, hashes)| QuestionSignature {
+                    line: line + 1,
+                    hashes,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let duplicates = find_duplicates(&[site], &options, 0.8);
+
+        assert_eq!(duplicates[0].len(), 1);
+        assert_eq!(duplicates[0][0].line, 2);
+        assert_eq!(duplicates[0][0].of.line, 1);
+        assert_eq!(duplicates[0][0].similarity, 0.875);
+    }
+
+    #[test]
+    fn test34_ranges_keep_line_numbers() {
+        let files = vec![write_numbered_questions("test34")];
+
+        let whole = ProcessOptions {
+            on_error: ErrorPolicy::Skip,
+            detect_duplicates: true,
+            exclude_duplicates: true,
+            ..Default::default()
+        };
+        let ranges = ProcessOptions {
+            chunk_bytes: 64,
+            ..whole.clone()
+        };
+        let expected = process_with_threads(&files, &whole, 1);
+        let split = process_with_threads(&files, &ranges, 4);
+
+        assert_eq!(split, expected);
+        assert_eq!(split[0].total_line_count, 194);
+        assert_eq!(split[0].skipped.sample_lines, vec![57, 150]);
+        assert_eq!(split[0].excluded_duplicate_count, 4);
+        let duplicate_lines: Vec<usize> = split[0]
+            .duplicates
+            .iter()
+            .map(|duplicate| duplicate.line)
+            .collect();
+        assert_eq!(duplicate_lines, vec![80, 120, 160, 200]);
+
+        let fail = ProcessOptions {
+            chunk_bytes: 64,
+            ..Default::default()
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .expect("Failed to build thread pool");
+        for _ in 0..10 {
+            let err = pool
+                .install(|| process_files_with(files.clone(), &fail))
+                .expect_err("Invalid line was not reported");
+            assert!(matches!(err, Error::Json { line: 57, .. }), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test35_batches_keep_line_numbers() {
+        let plain = write_numbered_questions("test35");
+        let compressed = plain.with_extension("jsonl.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&compressed).expect("Failed to create file"),
+            flate2::Compression::default(),
+        );
+        std::io::Write::write_all(
+            &mut encoder,
+            &std::fs::read(&plain).expect("Failed to read file"),
+        )
+        .expect("Failed to compress file");
+        encoder.finish().expect("Failed to compress file");
+
+        let options = ProcessOptions {
+            on_error: ErrorPolicy::Skip,
+            detect_duplicates: true,
+            exclude_duplicates: true,
+            ..Default::default()
+        };
+        let batches = ProcessOptions {
+            batch_bytes: 64,
+            ..options.clone()
+        };
+        let expected = process_with_threads(&[plain], &options, 1);
+        let streamed = process_with_threads(std::slice::from_ref(&compressed), &batches, 4);
+
+        assert_eq!(streamed, expected);
+        assert_eq!(streamed[0].skipped.sample_lines, vec![57, 150]);
+        assert_eq!(streamed[0].excluded_duplicate_count, 4);
+
+        let fail = ProcessOptions {
+            batch_bytes: 64,
+            ..Default::default()
+        };
+        let err =
+            process_files_with(vec![compressed], &fail).expect_err("Invalid line was not reported");
+        assert!(matches!(err, Error::Json { line: 57, .. }), "{}", err);
+    }
+
+    #[test]
+    fn test36_terms_are_pruned_while_a_file_is_counted() {
+        let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test36");
+        std::fs::create_dir_all(&directory).expect("Failed to create directory");
+        let path = directory.join("terms.jsonl");
+        // Every line has a common term and a rare one, and every 100th line a term that
+        // would be the second most frequent if the counts were exact.
+        let lines: Vec<String> = (1..=300)
+            .map(|line| {
+                let late = if line % 100 == 0 { " zlate" } else { "" };
+                format!(
+                    r#"{{"texts": ["common rare{}{}"], "tags": []}}"#,
+                    line, late
+                )
+            })
+            .collect();
+        std::fs::write(&path, lines.join("\n")).expect("Failed to write file");
+        let files = vec![path];
+        let options = ProcessOptions {
+            collect_terms: true,
+            max_terms: 2,
+            chunk_bytes: 1,
+            ..Default::default()
+        };
+
+        let single = process_with_threads(&files, &options, 1);
+        let parallel = process_with_threads(&files, &options, 4);
+
+        assert_eq!(single, parallel);
+        let terms = &single[0].terms;
+        assert_eq!(terms.len(), 2);
+        assert_eq!(terms.count("common"), 300);
+        assert_eq!(terms.count("zlate"), 0);
+    }
+
+    #[test]
+    fn test37_invalid_lines_are_not_excluded_duplicates() {
+        let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test37");
+        std::fs::create_dir_all(&directory).expect("Failed to create directory");
+        let path = directory.join("untagged.jsonl");
+        let lines = [
+            r#"{"texts": ["How do I sort a vector in Rust"], "tags": ["rust"]}"#,
+            r#"{"texts": ["Parse JSON in Python"], "tags": ["python"]}"#,
+            r#"{"texts": ["How do I sort a vector in Rust"]}"#,
+        ];
+        std::fs::write(&path, lines.join("\n")).expect("Failed to write file");
+        let files = vec![path];
+        let options = ProcessOptions {
+            on_error: ErrorPolicy::Skip,
+            require_fields: true,
+            detect_duplicates: true,
+            exclude_duplicates: true,
+            ..Default::default()
+        };
+
+        let file_data =
+            &process_files_with(files.clone(), &options).expect("Failed to process files")[0];
+        assert_eq!(file_data.total_line_count, 2);
+        assert_eq!(file_data.skipped.missing_field, 1);
+        assert_eq!(file_data.skipped.sample_lines, vec![3]);
+        assert_eq!(file_data.excluded_duplicate_count, 0);
+        assert!(file_data.duplicates.is_empty());
+
+        let options = ProcessOptions {
+            on_error: ErrorPolicy::Fail,
+            ..options
+        };
+        let err = process_files_with(files, &options).expect_err("Invalid line was counted");
+        assert!(matches!(err, Error::Schema { line: 3, .. }), "{}", err);
+    }
 }